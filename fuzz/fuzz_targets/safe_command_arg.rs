@@ -0,0 +1,33 @@
+//! Feeds arbitrary argument lists to `SafeCommand::arg` and checks the
+//! guarantees listed in the threat model of the `safe_command` tests.
+//!
+//! Run with `cargo fuzz run safe_command_arg` from the repository root.
+
+#![no_main]
+
+use botapp::desktop::safe_command::SafeCommand;
+use libfuzzer_sys::fuzz_target;
+
+const PROGRAMS: &[&str] = &["rclone", "notify-send", "osascript"];
+
+fuzz_target!(|input: (u8, Vec<String>)| {
+    let (program, args) = input;
+    let program = PROGRAMS[usize::from(program) % PROGRAMS.len()];
+    let Ok(mut cmd) = SafeCommand::new(program) else {
+        return;
+    };
+
+    for arg in args {
+        match cmd.arg(&arg) {
+            Ok(next) => {
+                assert!(!arg.is_empty() && arg.len() <= 4096);
+                assert!(!arg.contains('\0'));
+                if program != "notify-send" {
+                    assert!(!arg.chars().any(char::is_control));
+                }
+                cmd = next;
+            }
+            Err(_) => return,
+        }
+    }
+});
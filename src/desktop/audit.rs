@@ -0,0 +1,187 @@
+use super::storage;
+use serde::{Deserialize, Serialize};
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+const AUDIT_FILE: &str = "audit.jsonl";
+const MAX_FILE_BYTES: u64 = 1024 * 1024;
+const MAX_ROTATED_FILES: usize = 5;
+
+/// Shown in place of secret arguments.
+pub const REDACTED: &str = "***";
+
+static AUDIT_LOCK: Mutex<()> = Mutex::new(());
+
+/// One external process launched through `SafeCommand`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AuditRecord {
+    pub timestamp: String,
+    pub program: String,
+    /// Arguments with secret values replaced by [`REDACTED`].
+    pub args: Vec<String>,
+    /// Source location that ran the command.
+    pub caller: String,
+    /// `exited`, `spawned`, `timed_out`, `killed` or `failed`.
+    pub outcome: String,
+    pub exit_code: Option<i32>,
+    pub duration_ms: u64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+/// Append a record to the audit log. Failures are logged but never stop
+/// the command being audited.
+pub fn record(entry: &AuditRecord) {
+    let result = storage::app_data_dir().and_then(|dir| append(&dir, entry));
+    if let Err(e) = result {
+        log::warn!("Failed to write audit log: {e}");
+    }
+}
+
+/// Where `export_audit_log` wrote the log.
+#[derive(Debug, Clone, Serialize)]
+pub struct AuditExport {
+    pub path: String,
+    pub records: usize,
+}
+
+/// Write the whole audit log, oldest record first, to a new file in the
+/// downloads folder, or the app data directory when there is none. The
+/// webview cannot choose the path, and existing files are never replaced.
+///
+/// # Errors
+/// Returns an error if the log cannot be read or the export file cannot be
+/// created.
+#[tauri::command]
+pub fn export_audit_log() -> Result<AuditExport, String> {
+    let dir = storage::app_data_dir()?;
+    let destination = dirs::download_dir()
+        .unwrap_or_else(|| dir.clone())
+        .join(format!(
+            "botapp-audit-{}.jsonl",
+            chrono::Local::now().format("%Y%m%d-%H%M%S")
+        ));
+    let records = export(&dir, &destination)?;
+    Ok(AuditExport {
+        path: destination.to_string_lossy().to_string(),
+        records,
+    })
+}
+
+fn append(dir: &Path, entry: &AuditRecord) -> Result<(), String> {
+    let mut line = serde_json::to_string(entry).map_err(|e| format!("Failed to serialize: {e}"))?;
+    line.push('\n');
+
+    let _guard = AUDIT_LOCK
+        .lock()
+        .unwrap_or_else(std::sync::PoisonError::into_inner);
+
+    let path = dir.join(AUDIT_FILE);
+    if fs::metadata(&path).is_ok_and(|m| m.len() >= MAX_FILE_BYTES) {
+        rotate(dir)?;
+    }
+
+    let mut options = OpenOptions::new();
+    options.append(true).create(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+        options.mode(0o600);
+    }
+    options
+        .open(&path)
+        .and_then(|mut file| file.write_all(line.as_bytes()))
+        .map_err(|e| format!("Failed to write {}: {e}", path.display()))
+}
+
+/// Shift `audit.N.jsonl` to `audit.N+1.jsonl`, dropping the oldest, and
+/// make the current file `audit.1.jsonl`.
+fn rotate(dir: &Path) -> Result<(), String> {
+    let _ = fs::remove_file(rotated_path(dir, MAX_ROTATED_FILES));
+    for n in (1..MAX_ROTATED_FILES).rev() {
+        let from = rotated_path(dir, n);
+        if from.exists() {
+            fs::rename(&from, rotated_path(dir, n + 1))
+                .map_err(|e| format!("Failed to rotate audit log: {e}"))?;
+        }
+    }
+    fs::rename(dir.join(AUDIT_FILE), rotated_path(dir, 1))
+        .map_err(|e| format!("Failed to rotate audit log: {e}"))
+}
+
+fn rotated_path(dir: &Path, n: usize) -> PathBuf {
+    dir.join(format!("audit.{n}.jsonl"))
+}
+
+fn export(dir: &Path, destination: &Path) -> Result<usize, String> {
+    let _guard = AUDIT_LOCK
+        .lock()
+        .unwrap_or_else(std::sync::PoisonError::into_inner);
+
+    let files = (1..=MAX_ROTATED_FILES)
+        .rev()
+        .map(|n| rotated_path(dir, n))
+        .chain(std::iter::once(dir.join(AUDIT_FILE)));
+
+    let mut content = String::new();
+    for path in files.filter(|path| path.exists()) {
+        content.push_str(
+            &fs::read_to_string(&path)
+                .map_err(|e| format!("Failed to read {}: {e}", path.display()))?,
+        );
+    }
+
+    OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .open(destination)
+        .and_then(|mut file| file.write_all(content.as_bytes()))
+        .map_err(|e| format!("Failed to write {}: {e}", destination.display()))?;
+    Ok(content.lines().count())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(n: usize) -> AuditRecord {
+        AuditRecord {
+            timestamp: chrono::Utc::now().to_rfc3339(),
+            program: "rclone".to_string(),
+            args: vec!["version".to_string(), n.to_string()],
+            caller: "src/desktop/rclone.rs:1".to_string(),
+            outcome: "exited".to_string(),
+            exit_code: Some(0),
+            duration_ms: 1,
+            error: None,
+        }
+    }
+
+    #[test]
+    fn test_rotation_and_export() {
+        let dir =
+            std::env::temp_dir().join(format!("botapp-audit-{}", uuid::Uuid::new_v4().simple()));
+        fs::create_dir_all(&dir).unwrap();
+
+        let filler = "x".repeat(usize::try_from(MAX_FILE_BYTES).unwrap());
+        fs::write(dir.join(AUDIT_FILE), filler + "\n").unwrap();
+        append(&dir, &entry(1)).unwrap();
+        append(&dir, &entry(2)).unwrap();
+
+        assert!(rotated_path(&dir, 1).exists());
+        let current = fs::read_to_string(dir.join(AUDIT_FILE)).unwrap();
+        assert_eq!(current.lines().count(), 2);
+
+        let destination = dir.join("export.jsonl");
+        assert_eq!(export(&dir, &destination).unwrap(), 3);
+        let exported = fs::read_to_string(&destination).unwrap();
+        let last: AuditRecord = serde_json::from_str(exported.lines().last().unwrap()).unwrap();
+        assert_eq!(last.args, ["version", "2"]);
+        assert!(export(&dir, &destination).is_err());
+        assert_eq!(fs::read_to_string(&destination).unwrap(), exported);
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,356 @@
+use super::{provisioning, storage};
+use sha2::{Digest, Sha256};
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::SystemTime;
+
+/// Directories searched for allowed programs instead of `PATH`.
+#[cfg(target_os = "macos")]
+const SEARCH_PATH: &[&str] = &["/opt/homebrew/bin", "/usr/local/bin", "/usr/bin", "/bin"];
+#[cfg(all(unix, not(target_os = "macos")))]
+const SEARCH_PATH: &[&str] = &["/usr/local/bin", "/usr/bin", "/bin", "/snap/bin"];
+#[cfg(windows)]
+const SEARCH_PATH: &[&str] = &[r"C:\Program Files\rclone"];
+
+static RESOLVED: Mutex<BTreeMap<String, Resolved>> = Mutex::new(BTreeMap::new());
+
+/// A binary the app installed itself, trusted in addition to the search
+/// path and optionally pinned to its SHA-256.
+#[derive(Debug, Clone)]
+pub struct TrustedLocation {
+    pub path: PathBuf,
+    pub sha256: Option<String>,
+}
+
+/// A cached resolution, reused while the file keeps its size and
+/// modification time.
+struct Resolved {
+    path: PathBuf,
+    len: u64,
+    modified: Option<SystemTime>,
+}
+
+impl Resolved {
+    fn new(path: PathBuf) -> Result<Self, String> {
+        let metadata =
+            fs::metadata(&path).map_err(|e| format!("Cannot read {}: {e}", path.display()))?;
+        Ok(Self {
+            len: metadata.len(),
+            modified: metadata.modified().ok(),
+            path,
+        })
+    }
+
+    fn is_current(&self) -> bool {
+        fs::metadata(&self.path)
+            .is_ok_and(|m| m.len() == self.len && m.modified().ok() == self.modified)
+    }
+}
+
+/// Resolve `command`, a program name or an absolute path, to the executable
+/// to run. Names are looked up at the app's trusted location for `program`
+/// and then in [`SEARCH_PATH`]; absolute paths must lie in one of those
+/// directories. World-writable binaries and directories are rejected.
+///
+/// # Errors
+/// Returns an error if no trusted executable is found or it fails a check.
+pub fn resolve(command: &str, program: &str) -> Result<PathBuf, String> {
+    let mut resolved_guard = RESOLVED
+        .lock()
+        .unwrap_or_else(std::sync::PoisonError::into_inner);
+    if let Some(resolved) = resolved_guard.get(command) {
+        if resolved.is_current() {
+            return Ok(resolved.path.clone());
+        }
+    }
+
+    let mut trusted_dirs: Vec<PathBuf> = SEARCH_PATH.iter().map(PathBuf::from).collect();
+    if let Ok(dir) = provisioning::managed_dir() {
+        trusted_dirs.push(dir);
+    }
+    let location = match program {
+        "rclone" => provisioning::managed_location(),
+        _ => None,
+    };
+
+    let path = find_executable(command, &trusted_dirs, location.as_ref())?;
+    resolved_guard.insert(command.to_string(), Resolved::new(path.clone())?);
+    Ok(path)
+}
+
+/// Forget cached resolutions, after a managed binary changed.
+pub fn invalidate() {
+    RESOLVED
+        .lock()
+        .unwrap_or_else(std::sync::PoisonError::into_inner)
+        .clear();
+}
+
+fn find_executable(
+    command: &str,
+    trusted_dirs: &[PathBuf],
+    location: Option<&TrustedLocation>,
+) -> Result<PathBuf, String> {
+    let requested = Path::new(command);
+    let (path, pin) = if requested.is_absolute() {
+        let pin = location
+            .filter(|location| location.path == requested)
+            .and_then(|location| location.sha256.as_deref());
+        (requested.to_path_buf(), pin)
+    } else if requested.components().count() != 1 {
+        return Err(format!("Relative command paths are not allowed: {command}"));
+    } else if let Some(location) = location.filter(|location| location.path.is_file()) {
+        (location.path.clone(), location.sha256.as_deref())
+    } else {
+        let file_name = format!("{command}{}", std::env::consts::EXE_SUFFIX);
+        let found = trusted_dirs
+            .iter()
+            .map(|dir| dir.join(&file_name))
+            .find(|path| path.is_file())
+            .ok_or_else(|| format!("{command} not found in a trusted location"))?;
+        (found, None)
+    };
+
+    let parent = path
+        .parent()
+        .and_then(|dir| fs::canonicalize(dir).ok())
+        .ok_or_else(|| format!("Cannot resolve {}", path.display()))?;
+    let trusted = trusted_dirs
+        .iter()
+        .filter_map(|dir| fs::canonicalize(dir).ok())
+        .any(|dir| parent.starts_with(dir));
+    if !trusted {
+        return Err(format!("{} is not in a trusted location", path.display()));
+    }
+
+    // Check both where the command is found and, for symlinks, the binary
+    // it points to, but run it by the found path since some launchers
+    // dispatch on it.
+    let target =
+        fs::canonicalize(&path).map_err(|e| format!("Cannot resolve {}: {e}", path.display()))?;
+    check_permissions(&path)?;
+    check_permissions(&target)?;
+
+    if let Some(expected) = pin {
+        verify_sha256(&target, expected)?;
+    }
+    Ok(path)
+}
+
+/// Check that `dir` exists, lies within one of `roots` and is not
+/// world-writable, returning its canonical path.
+///
+/// # Errors
+/// Returns an error naming the check that failed.
+pub fn check_working_dir(dir: &Path, roots: &[PathBuf]) -> Result<PathBuf, String> {
+    let canonical =
+        fs::canonicalize(dir).map_err(|e| format!("Cannot resolve {}: {e}", dir.display()))?;
+    if !canonical.is_dir() {
+        return Err(format!("{} is not a directory", dir.display()));
+    }
+
+    let within = roots
+        .iter()
+        .filter_map(|root| fs::canonicalize(root).ok())
+        .any(|root| canonical.starts_with(root));
+    if !within {
+        return Err(format!(
+            "{} is outside the allowed directories",
+            dir.display()
+        ));
+    }
+
+    check_dir_permissions(&canonical)?;
+    Ok(canonical)
+}
+
+/// Directories commands may run in: the app data directory, the user's home
+/// and the temporary directory.
+#[must_use]
+pub fn working_dir_roots() -> Vec<PathBuf> {
+    let mut roots = vec![std::env::temp_dir()];
+    roots.extend(dirs::home_dir());
+    roots.extend(storage::app_data_dir().ok());
+    roots
+}
+
+/// Reject binaries that are not executable or are writable by anyone, and
+/// directories above them writable by anyone.
+#[cfg(unix)]
+fn check_permissions(path: &Path) -> Result<(), String> {
+    let file_mode = mode(path)?;
+    if file_mode & 0o111 == 0 {
+        return Err(format!("{} is not executable", path.display()));
+    }
+    if file_mode & 0o002 != 0 {
+        return Err(format!("{} is world-writable", path.display()));
+    }
+    check_ancestors(path)
+}
+
+#[cfg(not(unix))]
+fn check_permissions(path: &Path) -> Result<(), String> {
+    if path.is_file() {
+        Ok(())
+    } else {
+        Err(format!("{} is not a file", path.display()))
+    }
+}
+
+#[cfg(unix)]
+fn check_dir_permissions(dir: &Path) -> Result<(), String> {
+    if mode(dir)? & 0o002 != 0 {
+        return Err(format!("{} is world-writable", dir.display()));
+    }
+    check_ancestors(dir)
+}
+
+#[cfg(not(unix))]
+fn check_dir_permissions(_dir: &Path) -> Result<(), String> {
+    Ok(())
+}
+
+/// Reject directories above `path` that anyone can write to. Sticky
+/// directories such as `/tmp` are allowed since others cannot replace
+/// entries in them.
+#[cfg(unix)]
+fn check_ancestors(path: &Path) -> Result<(), String> {
+    for dir in path
+        .ancestors()
+        .skip(1)
+        .filter(|d| !d.as_os_str().is_empty())
+    {
+        let dir_mode = mode(dir)?;
+        if dir_mode & 0o002 != 0 && dir_mode & 0o1000 == 0 {
+            return Err(format!("{} is world-writable", dir.display()));
+        }
+    }
+    Ok(())
+}
+
+#[cfg(unix)]
+fn mode(path: &Path) -> Result<u32, String> {
+    use std::os::unix::fs::PermissionsExt;
+
+    fs::metadata(path)
+        .map(|m| m.permissions().mode())
+        .map_err(|e| format!("Cannot read {}: {e}", path.display()))
+}
+
+fn verify_sha256(path: &Path, expected: &str) -> Result<(), String> {
+    let content = fs::read(path).map_err(|e| format!("Cannot read {}: {e}", path.display()))?;
+    let actual = hex::encode(Sha256::digest(&content));
+    if actual.eq_ignore_ascii_case(expected) {
+        Ok(())
+    } else {
+        Err(format!(
+            "{} does not match its pinned SHA-256",
+            path.display()
+        ))
+    }
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+    use std::os::unix::fs::PermissionsExt;
+
+    fn scratch_dir() -> PathBuf {
+        let dir = std::env::temp_dir().join(format!(
+            "botapp-command-path-{}",
+            uuid::Uuid::new_v4().simple()
+        ));
+        fs::create_dir_all(&dir).unwrap();
+        fs::set_permissions(&dir, fs::Permissions::from_mode(0o755)).unwrap();
+        dir
+    }
+
+    fn executable(dir: &Path, name: &str, mode: u32) -> PathBuf {
+        let path = dir.join(name);
+        fs::write(&path, b"#!/bin/sh\n").unwrap();
+        fs::set_permissions(&path, fs::Permissions::from_mode(mode)).unwrap();
+        path
+    }
+
+    #[test]
+    fn test_find_in_trusted_dirs() {
+        let dir = scratch_dir();
+        let path = executable(&dir, "rclone", 0o755);
+        let trusted = [dir.clone()];
+
+        assert_eq!(find_executable("rclone", &trusted, None).unwrap(), path);
+        assert_eq!(
+            find_executable(path.to_str().unwrap(), &trusted, None).unwrap(),
+            path
+        );
+        assert!(find_executable("notify-send", &trusted, None).is_err());
+        assert!(find_executable("./rclone", &trusted, None).is_err());
+
+        let elsewhere = scratch_dir();
+        let other = executable(&elsewhere, "rclone", 0o755);
+        assert!(find_executable(other.to_str().unwrap(), &trusted, None).is_err());
+
+        fs::remove_dir_all(dir).unwrap();
+        fs::remove_dir_all(elsewhere).unwrap();
+    }
+
+    #[test]
+    fn test_reject_world_writable() {
+        let dir = scratch_dir();
+        let trusted = [dir.clone()];
+        executable(&dir, "rclone", 0o757);
+        assert!(find_executable("rclone", &trusted, None).is_err());
+
+        executable(&dir, "rclone", 0o755);
+        fs::set_permissions(&dir, fs::Permissions::from_mode(0o777)).unwrap();
+        assert!(find_executable("rclone", &trusted, None).is_err());
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_check_working_dir() {
+        let root = scratch_dir();
+        let roots = [root.clone()];
+        let work = root.join("work");
+        fs::create_dir(&work).unwrap();
+        fs::set_permissions(&work, fs::Permissions::from_mode(0o755)).unwrap();
+
+        assert_eq!(check_working_dir(&work, &roots).unwrap(), work);
+        assert!(check_working_dir(&root.join("missing"), &roots).is_err());
+        assert!(check_working_dir(&work.join("../.."), &roots).is_err());
+        assert!(check_working_dir(Path::new("/"), &roots).is_err());
+
+        let file = executable(&root, "file", 0o644);
+        assert!(check_working_dir(&file, &roots).is_err());
+
+        fs::set_permissions(&work, fs::Permissions::from_mode(0o777)).unwrap();
+        assert!(check_working_dir(&work, &roots).is_err());
+
+        fs::remove_dir_all(root).unwrap();
+    }
+
+    #[test]
+    fn test_sha256_pin() {
+        let dir = scratch_dir();
+        let path = executable(&dir, "rclone", 0o755);
+        let sha256 = hex::encode(Sha256::digest(b"#!/bin/sh\n"));
+        let trusted = [dir.clone()];
+
+        let pinned = TrustedLocation {
+            path: path.clone(),
+            sha256: Some(sha256),
+        };
+        assert!(find_executable("rclone", &trusted, Some(&pinned)).is_ok());
+
+        let wrong = TrustedLocation {
+            path,
+            sha256: Some("0".repeat(64)),
+        };
+        assert!(find_executable("rclone", &trusted, Some(&wrong)).is_err());
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,302 @@
+use super::sync::{parse_size, validate_age};
+use std::net::SocketAddr;
+use std::path::{Component, Path};
+
+/// Checks the value of a flag or a positional argument, returning why it is
+/// rejected.
+pub type Validator = fn(&str) -> Result<(), String>;
+
+/// What `SafeCommand` lets a program be called with. Arguments are checked
+/// as they are added: flags must be listed, flags in `value_flags` take the
+/// next argument (or `--flag=value`) as their value, and the first
+/// positional arguments must spell one of `subcommands`. When `flags` lists
+/// `--`, everything after it is positional.
+pub struct CommandPolicy {
+    pub name: &'static str,
+    pub subcommands: &'static [&'static [&'static str]],
+    pub flags: &'static [&'static str],
+    pub value_flags: &'static [(&'static str, Validator)],
+    pub positional: Validator,
+    /// Characters rejected anywhere in an argument.
+    pub forbidden: fn(char) -> bool,
+    /// Flags, without dashes, and `key value` option names whose value is
+    /// masked in the audit log.
+    pub secret_keys: &'static [&'static str],
+    /// Subcommands that read their secret positional argument from stdin
+    /// when given `-`, used by `SafeCommand::arg_secret`.
+    pub secret_stdin: &'static [&'static [&'static str]],
+    /// The environment variable the program reads a flag from, so secret
+    /// flag values stay out of argv.
+    pub flag_env: Option<fn(&str) -> String>,
+}
+
+/// rclone runs without a shell, so only control characters are rejected;
+/// filter patterns and remote paths may use `{}`, `()` and the like. Flags
+/// that change where rclone reads its config or writes files are left out.
+static RCLONE: CommandPolicy = CommandPolicy {
+    name: "rclone",
+    subcommands: &[
+        &["about"],
+        &["bisync"],
+        &["check"],
+        &["config", "create"],
+        &["config", "delete"],
+        &["config", "dump"],
+        &["config", "encryption", "remove"],
+        &["config", "encryption", "set"],
+        &["config", "update"],
+        &["listremotes"],
+        &["lsd"],
+        &["lsjson"],
+        &["obscure"],
+        &["rcd"],
+        &["sync"],
+        &["version"],
+    ],
+    flags: &[
+        "--checksum",
+        "--json",
+        "--no-mimetype",
+        "--non-interactive",
+        "--resync",
+        "--use-json-log",
+        "--verbose",
+    ],
+    value_flags: &[
+        ("--bwlimit", any_value),
+        ("--checkers", count),
+        ("--combined", stdout_or_absolute_path),
+        ("--filter-from", absolute_path),
+        ("--filters-file", absolute_path),
+        ("--max-age", validate_age),
+        ("--max-size", size),
+        ("--min-size", size),
+        ("--rc-addr", loopback_address),
+        ("--stats", any_value),
+        ("--transfers", count),
+    ],
+    positional: any_value,
+    forbidden: char::is_control,
+    secret_keys: &[
+        "bearer_token",
+        "client_secret",
+        "key_file_pass",
+        "pass",
+        "password",
+        "secret_access_key",
+        "session_token",
+        "token",
+    ],
+    secret_stdin: &[&["obscure"]],
+    flag_env: Some(rclone_flag_env),
+};
+
+/// Title and body of a desktop notification, after `--` so text starting
+/// with a dash is not taken for an option.
+static NOTIFY_SEND: CommandPolicy = CommandPolicy {
+    name: "notify-send",
+    subcommands: &[],
+    flags: &["--"],
+    value_flags: &[],
+    positional: any_value,
+    forbidden: is_nul,
+    secret_keys: &[],
+    secret_stdin: &[],
+    flag_env: None,
+};
+
+/// Only inline `display notification` scripts, never script files.
+static OSASCRIPT: CommandPolicy = CommandPolicy {
+    name: "osascript",
+    subcommands: &[],
+    flags: &[],
+    value_flags: &[("-e", notification_script)],
+    positional: no_value,
+    forbidden: char::is_control,
+    secret_keys: &[],
+    secret_stdin: &[],
+    flag_env: None,
+};
+
+/// The policy of an allowed program, by file name.
+#[must_use]
+pub fn policy_for(program: &str) -> Option<&'static CommandPolicy> {
+    [&RCLONE, &NOTIFY_SEND, &OSASCRIPT]
+        .into_iter()
+        .find(|policy| policy.name == program)
+}
+
+impl CommandPolicy {
+    /// Whether `path` is a complete subcommand, or `prefix_ok` and it is the
+    /// start of one.
+    #[must_use]
+    pub fn matches_subcommand(&self, path: &[String], prefix_ok: bool) -> bool {
+        self.subcommands.iter().any(|allowed| {
+            let fits = if prefix_ok {
+                allowed.len() >= path.len()
+            } else {
+                allowed.len() == path.len()
+            };
+            fits && allowed.iter().zip(path).all(|(a, p)| a == p)
+        })
+    }
+
+    /// Whether the value of `key`, a flag or option name, is secret.
+    #[must_use]
+    pub fn is_secret_key(&self, key: &str) -> bool {
+        self.secret_keys.contains(&key.trim_start_matches('-'))
+    }
+
+    #[must_use]
+    pub fn value_flag(&self, flag: &str) -> Option<(&'static str, Validator)> {
+        self.value_flags
+            .iter()
+            .copied()
+            .find(|(name, _)| *name == flag)
+    }
+}
+
+/// rclone reads `--some-flag` from `RCLONE_SOME_FLAG`.
+fn rclone_flag_env(flag: &str) -> String {
+    format!(
+        "RCLONE_{}",
+        flag.trim_start_matches('-')
+            .replace('-', "_")
+            .to_uppercase()
+    )
+}
+
+fn is_nul(c: char) -> bool {
+    c == '\0'
+}
+
+fn any_value(_: &str) -> Result<(), String> {
+    Ok(())
+}
+
+fn no_value(value: &str) -> Result<(), String> {
+    Err(format!("unexpected argument {value:?}"))
+}
+
+fn count(value: &str) -> Result<(), String> {
+    match value.parse::<u32>() {
+        Ok(n) if n > 0 && value.chars().all(|c| c.is_ascii_digit()) => Ok(()),
+        _ => Err(format!("expected a positive number, got {value:?}")),
+    }
+}
+
+fn size(value: &str) -> Result<(), String> {
+    parse_size(value).map(|_| ())
+}
+
+fn absolute_path(value: &str) -> Result<(), String> {
+    let path = Path::new(value);
+    if path.is_absolute() && !path.components().any(|c| c == Component::ParentDir) {
+        Ok(())
+    } else {
+        Err(format!("expected an absolute path, got {value:?}"))
+    }
+}
+
+fn stdout_or_absolute_path(value: &str) -> Result<(), String> {
+    if value == "-" {
+        Ok(())
+    } else {
+        absolute_path(value)
+    }
+}
+
+fn loopback_address(value: &str) -> Result<(), String> {
+    match value.parse::<SocketAddr>() {
+        Ok(addr) if addr.ip().is_loopback() => Ok(()),
+        _ => Err(format!("expected a loopback address, got {value:?}")),
+    }
+}
+
+/// Accept exactly `display notification "…" with title "…"`, where both
+/// parts are AppleScript string literals, so nothing else can be run.
+fn notification_script(script: &str) -> Result<(), String> {
+    let invalid = || "only `display notification` scripts are allowed".to_string();
+
+    let rest = script
+        .strip_prefix("display notification ")
+        .and_then(skip_string_literal)
+        .and_then(|rest| rest.strip_prefix(" with title "))
+        .and_then(skip_string_literal)
+        .ok_or_else(invalid)?;
+
+    if rest.is_empty() {
+        Ok(())
+    } else {
+        Err(invalid())
+    }
+}
+
+/// Quote text as an AppleScript string literal that [`notification_script`]
+/// accepts. Control characters become spaces since `osascript` arguments
+/// may not contain them.
+#[must_use]
+pub fn applescript_string(text: &str) -> String {
+    let mut quoted = String::with_capacity(text.len() + 2);
+    quoted.push('"');
+    for c in text.chars() {
+        match c {
+            '"' | '\\' => {
+                quoted.push('\\');
+                quoted.push(c);
+            }
+            c if c.is_control() => quoted.push(' '),
+            c => quoted.push(c),
+        }
+    }
+    quoted.push('"');
+    quoted
+}
+
+/// Skip a double-quoted AppleScript string with `\` escapes, returning what
+/// follows it.
+fn skip_string_literal(text: &str) -> Option<&str> {
+    let body = text.strip_prefix('"')?;
+    let mut escaped = false;
+    for (i, c) in body.char_indices() {
+        match (escaped, c) {
+            (true, _) => escaped = false,
+            (false, '\\') => escaped = true,
+            (false, '"') => return Some(&body[i + 1..]),
+            _ => {}
+        }
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_notification_script() {
+        assert!(notification_script(r#"display notification "Done" with title "Sync""#).is_ok());
+        assert!(notification_script(
+            r#"display notification "say \"hi\" \\ bye" with title "Sync""#
+        )
+        .is_ok());
+        assert!(notification_script(
+            r#"display notification "x" with title "y" & (do shell script "id")"#
+        )
+        .is_err());
+        assert!(notification_script(r#"display notification "x\" with title "y""#).is_err());
+        assert!(notification_script(r#"do shell script "id""#).is_err());
+    }
+
+    #[test]
+    fn test_value_validators() {
+        assert!(count("8").is_ok());
+        assert!(count("0").is_err());
+        assert!(count("+8").is_err());
+        assert!(loopback_address("127.0.0.1:5572").is_ok());
+        assert!(loopback_address("0.0.0.0:5572").is_err());
+        assert!(stdout_or_absolute_path("-").is_ok());
+        assert!(absolute_path("relative/file").is_err());
+        assert!(absolute_path("/data/../etc/passwd").is_err());
+    }
+}
@@ -0,0 +1,274 @@
+use super::sync::{get_sync_folder, SyncSide};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Component, Path, PathBuf};
+
+/// One side of a bisync conflict as found on disk.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ConflictVersion {
+    pub path: String,
+    pub size: u64,
+    pub modified: Option<String>,
+}
+
+/// A file changed on both sides. `local` and `remote` are the copies rclone
+/// renamed; `current` is the file still at the original path, if any.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SyncConflict {
+    pub path: String,
+    pub current: Option<ConflictVersion>,
+    pub local: Option<ConflictVersion>,
+    pub remote: Option<ConflictVersion>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ConflictResolution {
+    KeepLocal,
+    KeepRemote,
+    KeepBoth,
+}
+
+/// List the conflict copies left by `rclone bisync` in the sync folder.
+///
+/// # Errors
+/// Returns an error if the sync folder cannot be read.
+#[tauri::command]
+pub fn list_sync_conflicts(local_path: Option<String>) -> Result<Vec<SyncConflict>, String> {
+    let root = PathBuf::from(local_path.unwrap_or_else(get_sync_folder));
+    if !root.is_dir() {
+        return Err("Sync folder does not exist".into());
+    }
+
+    let mut conflicts: BTreeMap<String, SyncConflict> = BTreeMap::new();
+    collect_conflicts(&root, &root, &mut conflicts)?;
+
+    for conflict in conflicts.values_mut() {
+        let current = root.join(&conflict.path);
+        conflict.current = version_of(&root, &current);
+    }
+
+    Ok(conflicts.into_values().collect())
+}
+
+/// Resolve a conflict by keeping one side, or both under distinct names. The
+/// result reaches the other side on the next bisync run.
+///
+/// # Errors
+/// Returns an error if the path is invalid, no conflict exists for it, or a
+/// file cannot be moved or removed.
+#[tauri::command]
+pub fn resolve_conflict(
+    local_path: Option<String>,
+    path: &str,
+    resolution: ConflictResolution,
+) -> Result<(), String> {
+    let root = PathBuf::from(local_path.unwrap_or_else(get_sync_folder));
+    let relative = validate_relative_path(path)?;
+
+    let conflict = list_sync_conflicts(Some(root.to_string_lossy().to_string()))?
+        .into_iter()
+        .find(|c| Path::new(&c.path) == relative)
+        .ok_or_else(|| format!("No conflict found for {path}"))?;
+
+    let original = root.join(relative);
+    let local = conflict.local.map(|v| root.join(v.path));
+    let remote = conflict.remote.map(|v| root.join(v.path));
+
+    match resolution {
+        ConflictResolution::KeepLocal => keep_one(&original, local, remote),
+        ConflictResolution::KeepRemote => keep_one(&original, remote, local),
+        ConflictResolution::KeepBoth => {
+            if let Some(local) = local {
+                rename_copy(&local, &original, "local")?;
+            }
+            if let Some(remote) = remote {
+                rename_copy(&remote, &original, "remote")?;
+            }
+            Ok(())
+        }
+    }
+}
+
+/// Split a bisync conflict file name into the original name and the side the
+/// copy came from. Path1 is the local folder and Path2 the remote.
+///
+/// Recognises `name..path1` (rclone before 1.66), `name.conflict1` and
+/// `stem.conflict1.ext`.
+fn parse_conflict_name(file_name: &str) -> Option<(String, SyncSide)> {
+    let side_of = |n: &str| match n {
+        "1" => Some(SyncSide::Local),
+        "2" => Some(SyncSide::Remote),
+        _ => None,
+    };
+
+    if let Some((original, n)) = file_name.rsplit_once("..path") {
+        return side_of(n)
+            .filter(|_| !original.is_empty())
+            .map(|side| (original.to_string(), side));
+    }
+
+    let (before, after) = file_name.rsplit_once(".conflict")?;
+    if before.is_empty() {
+        return None;
+    }
+
+    let (n, extension) = after
+        .split_once('.')
+        .map_or((after, None), |(n, ext)| (n, Some(ext)));
+    let side = side_of(n)?;
+
+    let original = match extension {
+        Some(ext) => format!("{before}.{ext}"),
+        None => before.to_string(),
+    };
+    Some((original, side))
+}
+
+fn collect_conflicts(
+    root: &Path,
+    dir: &Path,
+    conflicts: &mut BTreeMap<String, SyncConflict>,
+) -> Result<(), String> {
+    for entry in fs::read_dir(dir).map_err(|e| e.to_string())? {
+        let entry = entry.map_err(|e| e.to_string())?;
+        let path = entry.path();
+        let Ok(file_type) = entry.file_type() else {
+            continue;
+        };
+
+        if file_type.is_dir() {
+            collect_conflicts(root, &path, conflicts)?;
+            continue;
+        }
+        if !file_type.is_file() {
+            continue;
+        }
+
+        let Some((original, side)) = entry.file_name().to_str().and_then(parse_conflict_name)
+        else {
+            continue;
+        };
+
+        let original_path = dir.join(original);
+        let key = relative_string(root, &original_path);
+        let conflict = conflicts.entry(key.clone()).or_insert(SyncConflict {
+            path: key,
+            current: None,
+            local: None,
+            remote: None,
+        });
+
+        let version = version_of(root, &path);
+        match side {
+            SyncSide::Local => conflict.local = version,
+            SyncSide::Remote => conflict.remote = version,
+        }
+    }
+
+    Ok(())
+}
+
+fn version_of(root: &Path, path: &Path) -> Option<ConflictVersion> {
+    let metadata = fs::metadata(path).ok().filter(fs::Metadata::is_file)?;
+    Some(ConflictVersion {
+        path: relative_string(root, path),
+        size: metadata.len(),
+        modified: metadata
+            .modified()
+            .ok()
+            .map(|t| chrono::DateTime::<chrono::Utc>::from(t).to_rfc3339()),
+    })
+}
+
+fn relative_string(root: &Path, path: &Path) -> String {
+    path.strip_prefix(root)
+        .unwrap_or(path)
+        .to_string_lossy()
+        .to_string()
+}
+
+fn validate_relative_path(path: &str) -> Result<&Path, String> {
+    let relative = Path::new(path);
+    let is_plain = relative
+        .components()
+        .all(|c| matches!(c, Component::Normal(_)));
+
+    if path.is_empty() || !is_plain {
+        return Err(format!("Invalid conflict path: {path}"));
+    }
+    Ok(relative)
+}
+
+fn keep_one(
+    original: &Path,
+    keep: Option<PathBuf>,
+    discard: Option<PathBuf>,
+) -> Result<(), String> {
+    if let Some(keep) = keep {
+        fs::rename(&keep, original).map_err(|e| format!("Failed to restore file: {e}"))?;
+    }
+    if let Some(discard) = discard {
+        fs::remove_file(&discard).map_err(|e| format!("Failed to remove conflict copy: {e}"))?;
+    }
+    Ok(())
+}
+
+fn rename_copy(copy: &Path, original: &Path, label: &str) -> Result<(), String> {
+    let stem = original
+        .file_stem()
+        .and_then(|s| s.to_str())
+        .ok_or("Invalid file name")?;
+    let name = match original.extension().and_then(|e| e.to_str()) {
+        Some(ext) => format!("{stem} ({label}).{ext}"),
+        None => format!("{stem} ({label})"),
+    };
+
+    let target = original.with_file_name(name);
+    if target.exists() {
+        return Err(format!("{} already exists", target.display()));
+    }
+    fs::rename(copy, &target).map_err(|e| format!("Failed to rename conflict copy: {e}"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_conflict_names() {
+        assert_eq!(
+            parse_conflict_name("report.docx..path1"),
+            Some(("report.docx".to_string(), SyncSide::Local))
+        );
+        assert_eq!(
+            parse_conflict_name("report.docx.conflict2"),
+            Some(("report.docx".to_string(), SyncSide::Remote))
+        );
+        assert_eq!(
+            parse_conflict_name("report.conflict1.docx"),
+            Some(("report.docx".to_string(), SyncSide::Local))
+        );
+        assert_eq!(
+            parse_conflict_name("Makefile.conflict2"),
+            Some(("Makefile".to_string(), SyncSide::Remote))
+        );
+    }
+
+    #[test]
+    fn test_parse_ignores_regular_files() {
+        assert_eq!(parse_conflict_name("report.docx"), None);
+        assert_eq!(parse_conflict_name("notes.conflict.txt"), None);
+        assert_eq!(parse_conflict_name("data.conflict3"), None);
+        assert_eq!(parse_conflict_name(".conflict1"), None);
+    }
+
+    #[test]
+    fn test_rejects_escaping_paths() {
+        assert!(validate_relative_path("../etc/passwd").is_err());
+        assert!(validate_relative_path("/etc/passwd").is_err());
+        assert!(validate_relative_path("").is_err());
+        assert!(validate_relative_path("docs/report.docx").is_ok());
+    }
+}
@@ -48,6 +48,13 @@ pub fn list_files(path: &str) -> Result<Vec<FileItem>, String> {
         });
     }
 
+    sort_file_items(&mut files);
+
+    Ok(files)
+}
+
+/// Sort folders first, then by case-insensitive name.
+pub fn sort_file_items(files: &mut [FileItem]) {
     files.sort_by(|a, b| {
         if a.is_dir && !b.is_dir {
             std::cmp::Ordering::Less
@@ -57,8 +64,6 @@ pub fn list_files(path: &str) -> Result<Vec<FileItem>, String> {
             a.name.to_lowercase().cmp(&b.name.to_lowercase())
         }
     });
-
-    Ok(files)
 }
 
 /// Upload a file to the specified destination.
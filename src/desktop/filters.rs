@@ -0,0 +1,210 @@
+use super::storage;
+use super::sync::{validate_profile_name, SyncConfig};
+use std::collections::{BTreeSet, HashMap};
+use std::fs;
+use std::path::{Component, Path, PathBuf};
+
+const SELECTIVE_SYNC_FILE: &str = "selective_sync.json";
+const FILTERS_DIR: &str = "filters";
+
+/// Characters with a meaning in rclone glob patterns.
+const GLOB_CHARS: [char; 7] = ['*', '?', '[', ']', '{', '}', '\\'];
+
+/// Remote folders a profile mirrors locally. An empty list syncs everything.
+#[tauri::command]
+#[must_use]
+pub fn get_selective_sync(profile: &str) -> Vec<String> {
+    load_selections().remove(profile).unwrap_or_default()
+}
+
+/// Choose the remote folders a profile mirrors locally. Folders are stored
+/// normalised, and folders inside another selected folder are dropped.
+///
+/// # Errors
+/// Returns an error if the profile name or a folder is invalid, or the
+/// selection cannot be saved.
+#[tauri::command]
+pub fn set_selective_sync(profile: &str, folders: Vec<String>) -> Result<Vec<String>, String> {
+    validate_profile_name(profile)?;
+    let folders = normalize_selection(&folders)?;
+
+    let mut selections = load_selections();
+    if folders.is_empty() {
+        selections.remove(profile);
+    } else {
+        selections.insert(profile.to_string(), folders.clone());
+    }
+    storage::save_json(SELECTIVE_SYNC_FILE, &selections)?;
+
+    Ok(folders)
+}
+
+/// Show the filter rules a sync with this configuration would use, in the
+/// order rclone applies them.
+///
+/// # Errors
+/// Returns an error if the configuration or the stored selection is invalid.
+#[tauri::command]
+pub fn preview_sync_filters(config: Option<SyncConfig>) -> Result<Vec<String>, String> {
+    render_rules(&config.unwrap_or_default())
+}
+
+/// Render the exclude patterns and folder selection of a profile as rclone
+/// filter rules: excludes first, then one include per selected folder and a
+/// final rule excluding everything else.
+///
+/// # Errors
+/// Returns an error if an exclude pattern or stored folder is invalid.
+pub fn render_rules(config: &SyncConfig) -> Result<Vec<String>, String> {
+    let mut rules = Vec::new();
+
+    for pattern in &config.exclude_patterns {
+        if pattern.trim().is_empty() || pattern.chars().any(char::is_control) {
+            return Err(format!("Invalid exclude pattern: {pattern:?}"));
+        }
+        rules.push(format!("- {pattern}"));
+    }
+
+    let folders = normalize_selection(&get_selective_sync(&config.profile))?;
+    for folder in &folders {
+        rules.push(format!("+ /{}/**", escape_glob(folder)));
+    }
+    if !folders.is_empty() {
+        rules.push("- **".to_string());
+    }
+
+    Ok(rules)
+}
+
+/// Write the rules of a profile to its filter file for `--filter-from` or
+/// bisync's `--filters-file`.
+///
+/// # Errors
+/// Returns an error if the file cannot be written.
+pub fn write_filter_file(profile: &str, rules: &[String]) -> Result<PathBuf, String> {
+    validate_profile_name(profile)?;
+    write_rules(&format!("{profile}.filter"), rules)
+}
+
+/// Write the rules of a profile for `preview_sync`, apart from the filter
+/// file a running sync may be reading.
+///
+/// # Errors
+/// Returns an error if the file cannot be written.
+pub fn write_preview_filter_file(profile: &str, rules: &[String]) -> Result<PathBuf, String> {
+    validate_profile_name(profile)?;
+    write_rules(&format!("{profile}.preview.filter"), rules)
+}
+
+fn write_rules(file_name: &str, rules: &[String]) -> Result<PathBuf, String> {
+    let dir = storage::app_data_dir()?.join(FILTERS_DIR);
+    fs::create_dir_all(&dir).map_err(|e| format!("Failed to create filters directory: {e}"))?;
+
+    let path = dir.join(file_name);
+    let mut content = rules.join("\n");
+    content.push('\n');
+    fs::write(&path, content).map_err(|e| format!("Failed to write filter file: {e}"))?;
+
+    Ok(path)
+}
+
+fn load_selections() -> HashMap<String, Vec<String>> {
+    storage::load_json(SELECTIVE_SYNC_FILE)
+}
+
+fn normalize_selection(folders: &[String]) -> Result<Vec<String>, String> {
+    let folders = folders
+        .iter()
+        .map(|f| normalize_folder(f))
+        .collect::<Result<BTreeSet<_>, _>>()?;
+
+    // Sorted order puts every folder before the folders nested in it.
+    let mut selection: Vec<String> = Vec::new();
+    for folder in folders {
+        let covered = selection
+            .iter()
+            .any(|parent| folder.starts_with(&format!("{parent}/")));
+        if !covered {
+            selection.push(folder);
+        }
+    }
+    Ok(selection)
+}
+
+/// Turn a folder picked on a remote into `a/b` form, relative to the remote
+/// root.
+///
+/// # Errors
+/// Returns an error if the folder is empty, contains control characters, or
+/// leaves the remote root through `..`.
+pub fn normalize_folder(folder: &str) -> Result<String, String> {
+    let trimmed = folder.trim().replace('\\', "/");
+    let trimmed = trimmed.trim_matches('/');
+
+    if trimmed.is_empty() || trimmed.chars().any(char::is_control) {
+        return Err(format!("Invalid folder: {folder:?}"));
+    }
+
+    let mut parts = Vec::new();
+    for component in Path::new(trimmed).components() {
+        match component {
+            Component::Normal(part) => parts.push(part.to_string_lossy().to_string()),
+            Component::CurDir => {}
+            _ => {
+                return Err(format!(
+                    "Folder must be relative to the remote root: {folder}"
+                ))
+            }
+        }
+    }
+
+    if parts.is_empty() {
+        return Err(format!("Invalid folder: {folder:?}"));
+    }
+    Ok(parts.join("/"))
+}
+
+fn escape_glob(folder: &str) -> String {
+    let mut escaped = String::with_capacity(folder.len());
+    for c in folder.chars() {
+        if GLOB_CHARS.contains(&c) {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+    escaped
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_normalize_selection() {
+        let folders = vec![
+            "/bots/sales/".to_string(),
+            "bots".to_string(),
+            "docs/./2024".to_string(),
+            "docs/2024".to_string(),
+        ];
+        assert_eq!(
+            normalize_selection(&folders).unwrap(),
+            vec!["bots".to_string(), "docs/2024".to_string()]
+        );
+    }
+
+    #[test]
+    fn test_rejects_escaping_folders() {
+        assert!(normalize_folder("../outside").is_err());
+        assert!(normalize_folder("docs/../../x").is_err());
+        assert!(normalize_folder("   ").is_err());
+        assert!(normalize_folder("a\nb").is_err());
+    }
+
+    #[test]
+    fn test_escape_glob() {
+        assert_eq!(escape_glob("Reports [2024]"), "Reports \\[2024\\]");
+        assert_eq!(escape_glob("{draft}*"), "\\{draft\\}\\*");
+        assert_eq!(escape_glob("plain/folder"), "plain/folder");
+    }
+}
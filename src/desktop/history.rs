@@ -0,0 +1,94 @@
+use super::storage;
+use super::sync::SyncMode;
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::PathBuf;
+
+const HISTORY_FILE: &str = "sync_history.json";
+const LOGS_DIR: &str = "sync_logs";
+const MAX_RUNS: usize = 100;
+
+/// A finished sync run. The log tail is kept in a separate file, read with
+/// `get_sync_run_log`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SyncRun {
+    pub id: String,
+    pub profile: String,
+    pub sync_mode: SyncMode,
+    pub started_at: String,
+    pub finished_at: String,
+    pub status: String,
+    pub exit_code: Option<i32>,
+    pub files_transferred: u64,
+    pub bytes_transferred: u64,
+    pub error_count: u64,
+    pub errors: Vec<String>,
+}
+
+/// Sync runs, newest first, optionally only those of one profile.
+#[tauri::command]
+#[must_use]
+pub fn get_sync_history(profile: Option<String>, limit: Option<usize>) -> Vec<SyncRun> {
+    load_history()
+        .into_iter()
+        .rev()
+        .filter(|run| profile.as_ref().is_none_or(|p| &run.profile == p))
+        .take(limit.unwrap_or(MAX_RUNS))
+        .collect()
+}
+
+/// The rclone log tail captured for a run.
+///
+/// # Errors
+/// Returns an error if the run id is invalid or its log is gone.
+#[tauri::command]
+pub fn get_sync_run_log(run_id: &str) -> Result<String, String> {
+    fs::read_to_string(log_path(run_id)?).map_err(|e| format!("No log for run {run_id}: {e}"))
+}
+
+/// The most recent run, optionally of one profile.
+#[must_use]
+pub fn last_run(profile: Option<&str>) -> Option<SyncRun> {
+    load_history()
+        .into_iter()
+        .rev()
+        .find(|run| profile.is_none_or(|p| run.profile == p))
+}
+
+/// Append a run and its log, dropping the oldest runs beyond the limit.
+///
+/// # Errors
+/// Returns an error if the history or the log cannot be written.
+pub fn record_run(run: SyncRun, log: &[String]) -> Result<(), String> {
+    let dir = storage::app_data_dir()?.join(LOGS_DIR);
+    fs::create_dir_all(&dir).map_err(|e| format!("Failed to create log directory: {e}"))?;
+
+    let mut content = log.join("\n");
+    content.push('\n');
+    fs::write(log_path(&run.id)?, content).map_err(|e| format!("Failed to write run log: {e}"))?;
+
+    let mut history = load_history();
+    history.push(run);
+
+    let excess = history.len().saturating_sub(MAX_RUNS);
+    for old in history.drain(..excess) {
+        if let Ok(path) = log_path(&old.id) {
+            let _ = fs::remove_file(path);
+        }
+    }
+
+    storage::save_json(HISTORY_FILE, &history)
+}
+
+fn load_history() -> Vec<SyncRun> {
+    storage::load_json(HISTORY_FILE)
+}
+
+fn log_path(run_id: &str) -> Result<PathBuf, String> {
+    if run_id.is_empty() || !run_id.chars().all(|c| c.is_ascii_alphanumeric()) {
+        return Err(format!("Invalid run id: {run_id}"));
+    }
+    Ok(storage::app_data_dir()?
+        .join(LOGS_DIR)
+        .join(format!("{run_id}.log")))
+}
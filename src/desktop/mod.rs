@@ -1,4 +1,19 @@
+pub mod audit;
+pub mod command_path;
+pub mod command_policy;
+pub mod conflicts;
 pub mod drive;
+pub mod filters;
+pub mod history;
+pub mod native_sync;
+pub mod provisioning;
+pub mod rc;
+pub mod rclone;
+pub mod remote;
+pub mod s3;
 pub mod safe_command;
+pub mod sandbox;
+pub mod secrets;
+pub mod storage;
 pub mod sync;
 pub mod tray;
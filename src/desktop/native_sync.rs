@@ -0,0 +1,1248 @@
+use super::s3::S3Store;
+use super::{secrets, storage};
+use md5::{Digest, Md5};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet};
+use std::fs::{self, File, OpenOptions};
+use std::future::Future;
+use std::io::{Read, Seek, SeekFrom, Write};
+use std::path::{Component, Path, PathBuf};
+use std::sync::Mutex;
+use std::time::UNIX_EPOCH;
+
+static RUNNING_PROFILES: Mutex<BTreeSet<String>> = Mutex::new(BTreeSet::new());
+
+/// Files at least this large are uploaded in parts, so an interrupted upload
+/// resumes from the last finished part.
+const MULTIPART_THRESHOLD: u64 = 16 * 1024 * 1024;
+const MIN_PART_SIZE: u64 = 8 * 1024 * 1024;
+const MAX_PARTS: u64 = 10_000;
+/// Downloads are written next to their target under this suffix and renamed
+/// into place once complete. An interrupted download continues from the end
+/// of its partial file.
+const PARTIAL_SUFFIX: &str = ".botapp-part";
+
+/// Settings of the built-in engine, which syncs a local folder with an
+/// S3-compatible bucket without rclone.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct NativeSyncConfig {
+    #[serde(default = "default_profile")]
+    pub profile: String,
+    pub local_path: String,
+    pub endpoint: String,
+    #[serde(default = "default_region")]
+    pub region: String,
+    pub bucket: String,
+    /// Key prefix inside the bucket that maps to the local folder.
+    #[serde(default)]
+    pub prefix: String,
+    pub access_key_id: String,
+}
+
+fn default_profile() -> String {
+    "default".to_string()
+}
+
+fn default_region() -> String {
+    "us-east-1".to_string()
+}
+
+/// An object in the store, keyed relative to the synced prefix.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RemoteObject {
+    pub key: String,
+    pub etag: String,
+    pub size: u64,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CompletedPart {
+    pub part_number: u32,
+    pub etag: String,
+}
+
+/// The content of an object, read as it arrives.
+pub trait ObjectBody: Send {
+    /// Where in the object the body starts, which is 0 when the store
+    /// ignored the requested offset.
+    fn offset(&self) -> u64;
+    /// The next chunk, or `None` at the end of the object.
+    fn next_chunk(&mut self) -> impl Future<Output = Result<Option<Vec<u8>>, String>> + Send;
+}
+
+/// The subset of the S3 API the engine uses. Implemented by [`S3Store`] and
+/// by an in-memory store in the tests.
+pub trait ObjectStore: Sync {
+    type Body: ObjectBody;
+
+    fn list(&self) -> impl Future<Output = Result<Vec<RemoteObject>, String>> + Send;
+    /// Read an object from byte `offset` on. Reading from an offset fails
+    /// if the object no longer has `etag`.
+    fn get(
+        &self,
+        key: &str,
+        etag: &str,
+        offset: u64,
+    ) -> impl Future<Output = Result<Self::Body, String>> + Send;
+    /// Store an object and return its ETag.
+    fn put(&self, key: &str, body: Vec<u8>) -> impl Future<Output = Result<String, String>> + Send;
+    fn delete(&self, key: &str) -> impl Future<Output = Result<(), String>> + Send;
+    fn create_multipart(&self, key: &str) -> impl Future<Output = Result<String, String>> + Send;
+    fn upload_part(
+        &self,
+        key: &str,
+        upload_id: &str,
+        part_number: u32,
+        body: Vec<u8>,
+    ) -> impl Future<Output = Result<String, String>> + Send;
+    fn list_parts(
+        &self,
+        key: &str,
+        upload_id: &str,
+    ) -> impl Future<Output = Result<Vec<CompletedPart>, String>> + Send;
+    fn complete_multipart(
+        &self,
+        key: &str,
+        upload_id: &str,
+        parts: &[CompletedPart],
+    ) -> impl Future<Output = Result<String, String>> + Send;
+    fn abort_multipart(
+        &self,
+        key: &str,
+        upload_id: &str,
+    ) -> impl Future<Output = Result<(), String>> + Send;
+}
+
+/// What the engine knows about each file: its state on both sides after the
+/// last successful sync, plus multipart uploads and downloads that can be
+/// resumed.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct SyncState {
+    pub files: BTreeMap<String, FileState>,
+    #[serde(default)]
+    pub uploads: BTreeMap<String, PendingUpload>,
+    #[serde(default)]
+    pub downloads: BTreeMap<String, PendingDownload>,
+}
+
+/// A file as it was when both sides last agreed. `hash` is the MD5 of the
+/// content, which is also the ETag of objects uploaded in one part.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct FileState {
+    pub size: u64,
+    pub modified: u64,
+    pub hash: String,
+    pub etag: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PendingUpload {
+    pub upload_id: String,
+    pub hash: String,
+}
+
+/// The object version a partial file holds the start of.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PendingDownload {
+    pub etag: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LocalFile {
+    pub size: u64,
+    pub modified: u64,
+    pub hash: String,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Action {
+    Upload,
+    Download,
+    DeleteLocal,
+    DeleteRemote,
+    /// Both sides changed: the local copy is renamed and uploaded, and the
+    /// remote version is downloaded in its place.
+    Conflict,
+    /// Both sides already hold the same content; only the state is updated.
+    Record,
+    /// The file is gone from both sides; only the state is updated.
+    Forget,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PlannedAction {
+    pub path: String,
+    pub action: Action,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct NativeSyncReport {
+    pub uploaded: u64,
+    pub downloaded: u64,
+    pub deleted_local: u64,
+    pub deleted_remote: u64,
+    pub conflicts: Vec<String>,
+    pub bytes_transferred: u64,
+    pub errors: Vec<String>,
+}
+
+/// Sync a local folder with an S3 bucket using the built-in engine, for
+/// machines where rclone is not available.
+///
+/// # Errors
+/// Returns an error if the configuration is invalid, the profile is already
+/// syncing, or either side cannot be listed. Failures of single files are
+/// reported in the result.
+#[tauri::command]
+pub async fn native_sync(config: NativeSyncConfig) -> Result<NativeSyncReport, String> {
+    let store = open_store(&config)?;
+    let _running = ProfileLock::acquire(&config.profile)?;
+    let root = prepare_root(&config.local_path)?;
+
+    let state_file = state_file(&config.profile);
+    let mut state: SyncState = storage::load_json(&state_file);
+    let persist = |state: &SyncState| storage::save_json(&state_file, state);
+
+    run_sync(&store, &root, &mut state, &persist).await
+}
+
+/// List what `native_sync` would do, without changing anything.
+///
+/// # Errors
+/// Returns an error if the configuration is invalid or either side cannot be
+/// listed.
+#[tauri::command]
+pub async fn preview_native_sync(config: NativeSyncConfig) -> Result<Vec<PlannedAction>, String> {
+    let store = open_store(&config)?;
+    let root = prepare_root(&config.local_path)?;
+    let state: SyncState = storage::load_json(&state_file(&config.profile));
+
+    let local = scan_local(&root, &state)?;
+    let remote = list_remote(&store).await?;
+    Ok(plan(&local, &remote, &state.files))
+}
+
+/// Store the S3 secret key of a native sync profile in the secret store.
+///
+/// # Errors
+/// Returns an error if the profile name is invalid or the secret cannot be
+/// stored.
+#[tauri::command]
+pub fn set_native_sync_secret(profile: &str, secret_access_key: &str) -> Result<(), String> {
+    super::sync::validate_profile_name(profile)?;
+    secrets::set_secret(&secret_key(profile), secret_access_key)
+}
+
+/// Scan, plan and execute one sync. `persist` is called whenever the state
+/// changes, so an interrupted run resumes where it stopped.
+///
+/// # Errors
+/// Returns an error if either side cannot be listed.
+pub async fn run_sync<S, P>(
+    store: &S,
+    root: &Path,
+    state: &mut SyncState,
+    persist: &P,
+) -> Result<NativeSyncReport, String>
+where
+    S: ObjectStore,
+    P: Fn(&SyncState) -> Result<(), String> + Sync,
+{
+    let local = scan_local(root, state)?;
+    let remote = list_remote(store).await?;
+    let actions = plan(&local, &remote, &state.files);
+
+    let mut report = NativeSyncReport::default();
+    for PlannedAction { path, action } in actions {
+        let result = execute(store, root, &path, action, &local, &remote, state, persist).await;
+        match result {
+            Ok(bytes) => {
+                report.bytes_transferred += bytes;
+                match action {
+                    Action::Upload => report.uploaded += 1,
+                    Action::Download => report.downloaded += 1,
+                    Action::DeleteLocal => report.deleted_local += 1,
+                    Action::DeleteRemote => report.deleted_remote += 1,
+                    Action::Conflict => report.conflicts.push(path),
+                    Action::Record | Action::Forget => {}
+                }
+            }
+            Err(e) => report.errors.push(format!("{path}: {e}")),
+        }
+        persist(state)?;
+    }
+
+    Ok(report)
+}
+
+/// Three-way diff of the local files, the remote objects and the state of
+/// the last sync. A side counts as changed when its hash or ETag differs
+/// from the recorded one; a file changed on both sides is a conflict unless
+/// the contents are identical.
+#[must_use]
+pub fn plan(
+    local: &BTreeMap<String, LocalFile>,
+    remote: &BTreeMap<String, RemoteObject>,
+    base: &BTreeMap<String, FileState>,
+) -> Vec<PlannedAction> {
+    let paths: BTreeSet<&String> = local
+        .keys()
+        .chain(remote.keys())
+        .chain(base.keys())
+        .collect();
+
+    let mut actions = Vec::new();
+    for path in paths {
+        let (l, r, b) = (local.get(path), remote.get(path), base.get(path));
+        let local_changed = |l: &LocalFile, b: &FileState| l.hash != b.hash;
+        let remote_changed = |r: &RemoteObject, b: &FileState| r.etag != b.etag;
+
+        let action = match (l, r, b) {
+            (Some(_), None, None) => Action::Upload,
+            (None, Some(_), None) => Action::Download,
+            (Some(l), Some(r), None) => {
+                if same_content(l, r) {
+                    Action::Record
+                } else {
+                    Action::Conflict
+                }
+            }
+            (Some(l), Some(r), Some(b)) => match (local_changed(l, b), remote_changed(r, b)) {
+                (false, false) => continue,
+                (true, false) => Action::Upload,
+                (false, true) => Action::Download,
+                (true, true) if same_content(l, r) => Action::Record,
+                (true, true) => Action::Conflict,
+            },
+            // Deleted remotely: keep local edits made since the last sync.
+            (Some(l), None, Some(b)) => {
+                if local_changed(l, b) {
+                    Action::Upload
+                } else {
+                    Action::DeleteLocal
+                }
+            }
+            (None, Some(r), Some(b)) => {
+                if remote_changed(r, b) {
+                    Action::Download
+                } else {
+                    Action::DeleteRemote
+                }
+            }
+            (None, None, Some(_)) => Action::Forget,
+            (None, None, None) => continue,
+        };
+
+        actions.push(PlannedAction {
+            path: path.clone(),
+            action,
+        });
+    }
+    actions
+}
+
+/// Whether an object holds the local content. Only ETags of single-part
+/// uploads are MD5 hashes, so multipart objects never compare equal.
+fn same_content(local: &LocalFile, remote: &RemoteObject) -> bool {
+    local.size == remote.size && remote.etag.trim_matches('"') == local.hash
+}
+
+#[allow(clippy::too_many_arguments)]
+async fn execute<S, P>(
+    store: &S,
+    root: &Path,
+    path: &str,
+    action: Action,
+    local: &BTreeMap<String, LocalFile>,
+    remote: &BTreeMap<String, RemoteObject>,
+    state: &mut SyncState,
+    persist: &P,
+) -> Result<u64, String>
+where
+    S: ObjectStore,
+    P: Fn(&SyncState) -> Result<(), String> + Sync,
+{
+    let local_file = || {
+        local
+            .get(path)
+            .ok_or_else(|| "missing local file".to_string())
+    };
+    let remote_object = || remote.get(path).ok_or_else(|| "missing object".to_string());
+
+    match action {
+        Action::Upload => upload(store, root, path, local_file()?, state, persist).await,
+        Action::Download => download(store, root, path, remote_object()?, state, persist).await,
+        Action::DeleteLocal => {
+            fs::remove_file(local_path(root, path)?)
+                .map_err(|e| format!("Failed to delete local file: {e}"))?;
+            state.files.remove(path);
+            Ok(0)
+        }
+        Action::DeleteRemote => {
+            store.delete(path).await?;
+            state.files.remove(path);
+            Ok(0)
+        }
+        Action::Conflict => {
+            let copy = conflict_name(path, &chrono::Local::now());
+            fs::rename(local_path(root, path)?, local_path(root, &copy)?)
+                .map_err(|e| format!("Failed to keep conflicting copy: {e}"))?;
+
+            let downloaded = download(store, root, path, remote_object()?, state, persist).await?;
+            let uploaded = upload(store, root, &copy, local_file()?, state, persist).await?;
+            Ok(downloaded + uploaded)
+        }
+        Action::Record => {
+            let (l, r) = (local_file()?, remote_object()?);
+            state.files.insert(path.to_string(), file_state(l, &r.etag));
+            Ok(0)
+        }
+        Action::Forget => {
+            state.files.remove(path);
+            Ok(0)
+        }
+    }
+}
+
+async fn upload<S, P>(
+    store: &S,
+    root: &Path,
+    key: &str,
+    file: &LocalFile,
+    state: &mut SyncState,
+    persist: &P,
+) -> Result<u64, String>
+where
+    S: ObjectStore,
+    P: Fn(&SyncState) -> Result<(), String> + Sync,
+{
+    let path = local_path(root, key)?;
+    let etag = if file.size >= MULTIPART_THRESHOLD {
+        upload_multipart(store, &path, key, file, state, persist).await?
+    } else {
+        let body = fs::read(&path).map_err(|e| format!("Failed to read file: {e}"))?;
+        store.put(key, body).await?
+    };
+
+    state.files.insert(key.to_string(), file_state(file, &etag));
+    Ok(file.size)
+}
+
+/// Upload in parts, resuming a previous upload of the same content by asking
+/// the store which parts it already has.
+async fn upload_multipart<S, P>(
+    store: &S,
+    path: &Path,
+    key: &str,
+    file: &LocalFile,
+    state: &mut SyncState,
+    persist: &P,
+) -> Result<String, String>
+where
+    S: ObjectStore,
+    P: Fn(&SyncState) -> Result<(), String> + Sync,
+{
+    let mut resumed = None;
+    if let Some(pending) = state.uploads.remove(key) {
+        if pending.hash == file.hash {
+            if let Ok(parts) = store.list_parts(key, &pending.upload_id).await {
+                resumed = Some((pending.upload_id, parts));
+            }
+        } else if let Err(e) = store.abort_multipart(key, &pending.upload_id).await {
+            log::warn!("Failed to abort stale upload of {key}: {e}");
+        }
+    }
+
+    let (upload_id, mut parts) = match resumed {
+        Some(resumed) => resumed,
+        None => (store.create_multipart(key).await?, Vec::new()),
+    };
+    state.uploads.insert(
+        key.to_string(),
+        PendingUpload {
+            upload_id: upload_id.clone(),
+            hash: file.hash.clone(),
+        },
+    );
+    persist(state)?;
+
+    let part_size = part_size(file.size);
+    let mut reader = File::open(path).map_err(|e| format!("Failed to open file: {e}"))?;
+    let mut offset = 0;
+    let mut part_number = 0;
+    while offset < file.size {
+        part_number += 1;
+        let len = part_size.min(file.size - offset);
+        if !parts.iter().any(|p| p.part_number == part_number) {
+            let mut body = vec![0; usize::try_from(len).map_err(|e| e.to_string())?];
+            reader
+                .seek(SeekFrom::Start(offset))
+                .and_then(|_| reader.read_exact(&mut body))
+                .map_err(|e| format!("Failed to read file: {e}"))?;
+
+            let etag = store
+                .upload_part(key, &upload_id, part_number, body)
+                .await?;
+            parts.push(CompletedPart { part_number, etag });
+        }
+        offset += len;
+    }
+
+    parts.sort_by_key(|p| p.part_number);
+    let etag = store.complete_multipart(key, &upload_id, &parts).await?;
+    state.uploads.remove(key);
+    Ok(etag)
+}
+
+/// Parts are at least 8 MiB, and larger for files that would otherwise need
+/// more parts than S3 allows. The size only depends on the file size, so a
+/// resumed upload splits the file the same way.
+fn part_size(size: u64) -> u64 {
+    MIN_PART_SIZE.max(size.div_ceil(MAX_PARTS))
+}
+
+/// Stream an object into its partial file and move it into place once
+/// complete. A partial file left by an interrupted download of the same
+/// object version is continued from its length. Returns the bytes received.
+async fn download<S, P>(
+    store: &S,
+    root: &Path,
+    key: &str,
+    object: &RemoteObject,
+    state: &mut SyncState,
+    persist: &P,
+) -> Result<u64, String>
+where
+    S: ObjectStore,
+    P: Fn(&SyncState) -> Result<(), String> + Sync,
+{
+    let path = local_path(root, key)?;
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent).map_err(|e| format!("Failed to create directory: {e}"))?;
+    }
+    let mut partial = path.clone().into_os_string();
+    partial.push(PARTIAL_SUFFIX);
+    let partial = PathBuf::from(partial);
+
+    let resumable = state
+        .downloads
+        .get(key)
+        .is_some_and(|pending| pending.etag == object.etag);
+    let offset = if resumable {
+        fs::metadata(&partial).map_or(0, |m| m.len())
+    } else {
+        0
+    };
+    let offset = if offset > object.size { 0 } else { offset };
+
+    state.downloads.insert(
+        key.to_string(),
+        PendingDownload {
+            etag: object.etag.clone(),
+        },
+    );
+    persist(state)?;
+
+    let write_error = |e: std::io::Error| format!("Failed to write file: {e}");
+    let mut file = OpenOptions::new()
+        .create(true)
+        .truncate(false)
+        .write(true)
+        .open(&partial)
+        .map_err(write_error)?;
+
+    let mut received = 0;
+    // A partial file that already holds the whole object is only renamed.
+    if offset == 0 || offset < object.size {
+        let mut body = store.get(key, &object.etag, offset).await?;
+        let start = body.offset();
+        file.set_len(start)
+            .and_then(|()| file.seek(SeekFrom::Start(start)))
+            .map_err(write_error)?;
+        while let Some(chunk) = body.next_chunk().await? {
+            file.write_all(&chunk).map_err(write_error)?;
+            received += chunk.len() as u64;
+        }
+    }
+    file.sync_all().map_err(write_error)?;
+    drop(file);
+    fs::rename(&partial, &path).map_err(write_error)?;
+    state.downloads.remove(key);
+
+    let metadata = fs::metadata(&path).map_err(|e| format!("Failed to read file: {e}"))?;
+    let file = LocalFile {
+        size: metadata.len(),
+        modified: modified_millis(&metadata),
+        hash: hash_file(&path)?,
+    };
+    state
+        .files
+        .insert(key.to_string(), file_state(&file, &object.etag));
+    Ok(received)
+}
+
+/// Walk the local folder. Hashes are reused from the state for files whose
+/// size and modification time did not change.
+///
+/// Links and other special files are not synced. A file that cannot be read
+/// must never look deleted, so it fails the scan instead of being skipped.
+///
+/// # Errors
+/// Returns an error if the folder or an entry cannot be read, or a synced
+/// file or folder was replaced by something that is not synced.
+pub fn scan_local(root: &Path, state: &SyncState) -> Result<BTreeMap<String, LocalFile>, String> {
+    let mut files = BTreeMap::new();
+    let mut dirs = vec![root.to_path_buf()];
+    let unreadable =
+        |path: &Path, e: std::io::Error| format!("Failed to read {}: {e}", path.display());
+
+    while let Some(dir) = dirs.pop() {
+        let entries = fs::read_dir(&dir).map_err(|e| unreadable(&dir, e))?;
+        for entry in entries {
+            let entry = entry.map_err(|e| unreadable(&dir, e))?;
+            let path = entry.path();
+            let file_type = entry.file_type().map_err(|e| unreadable(&path, e))?;
+            if file_type.is_dir() {
+                dirs.push(path);
+                continue;
+            }
+            if path.to_string_lossy().ends_with(PARTIAL_SUFFIX) {
+                continue;
+            }
+            let Some(key) = relative_key(root, &path) else {
+                // Object keys are UTF-8, so the state cannot refer to it.
+                log::warn!("Not syncing {}: name is not valid UTF-8", path.display());
+                continue;
+            };
+            if !file_type.is_file() {
+                if is_synced(state, &key) {
+                    return Err(format!(
+                        "{} was synced but is no longer a regular file",
+                        path.display()
+                    ));
+                }
+                continue;
+            }
+            let metadata = entry.metadata().map_err(|e| unreadable(&path, e))?;
+
+            let size = metadata.len();
+            let modified = modified_millis(&metadata);
+            let hash = match state.files.get(&key) {
+                Some(known) if known.size == size && known.modified == modified => {
+                    known.hash.clone()
+                }
+                _ => hash_file(&path)?,
+            };
+            files.insert(
+                key,
+                LocalFile {
+                    size,
+                    modified,
+                    hash,
+                },
+            );
+        }
+    }
+
+    Ok(files)
+}
+
+/// Whether the last sync recorded `key` as a file or a folder.
+fn is_synced(state: &SyncState, key: &str) -> bool {
+    let folder = format!("{key}/");
+    state.files.contains_key(key) || state.files.keys().any(|k| k.starts_with(&folder))
+}
+
+async fn list_remote<S: ObjectStore>(store: &S) -> Result<BTreeMap<String, RemoteObject>, String> {
+    Ok(store
+        .list()
+        .await?
+        .into_iter()
+        .filter(|object| local_path(Path::new(""), &object.key).is_ok())
+        .map(|object| (object.key.clone(), object))
+        .collect())
+}
+
+fn hash_file(path: &Path) -> Result<String, String> {
+    let mut file =
+        File::open(path).map_err(|e| format!("Failed to open {}: {e}", path.display()))?;
+    let mut hasher = Md5::new();
+    let mut buffer = vec![0; 1024 * 1024];
+    loop {
+        let read = file
+            .read(&mut buffer)
+            .map_err(|e| format!("Failed to read {}: {e}", path.display()))?;
+        if read == 0 {
+            break;
+        }
+        hasher.update(&buffer[..read]);
+    }
+    Ok(hex::encode(hasher.finalize()))
+}
+
+fn file_state(file: &LocalFile, etag: &str) -> FileState {
+    FileState {
+        size: file.size,
+        modified: file.modified,
+        hash: file.hash.clone(),
+        etag: etag.to_string(),
+    }
+}
+
+fn modified_millis(metadata: &fs::Metadata) -> u64 {
+    metadata
+        .modified()
+        .ok()
+        .and_then(|t| t.duration_since(UNIX_EPOCH).ok())
+        .map_or(0, |d| u64::try_from(d.as_millis()).unwrap_or(u64::MAX))
+}
+
+fn relative_key(root: &Path, path: &Path) -> Option<String> {
+    let relative = path.strip_prefix(root).ok()?;
+    let parts: Option<Vec<&str>> = relative
+        .components()
+        .map(|c| c.as_os_str().to_str())
+        .collect();
+    Some(parts?.join("/"))
+}
+
+/// Map an object key to a path inside the local folder, rejecting keys that
+/// would escape it.
+fn local_path(root: &Path, key: &str) -> Result<PathBuf, String> {
+    let invalid = || format!("Unsafe object key: {key}");
+    if key.is_empty() || key.ends_with('/') || key.contains('\\') || key.contains('\0') {
+        return Err(invalid());
+    }
+
+    let mut path = root.to_path_buf();
+    for component in Path::new(key).components() {
+        match component {
+            Component::Normal(part) => path.push(part),
+            _ => return Err(invalid()),
+        }
+    }
+    Ok(path)
+}
+
+/// `docs/report.pdf` becomes `docs/report (conflict 2026-10-18 101500).pdf`.
+fn conflict_name<Tz: chrono::TimeZone>(key: &str, now: &chrono::DateTime<Tz>) -> String
+where
+    Tz::Offset: std::fmt::Display,
+{
+    let stamp = now.format("%Y-%m-%d %H%M%S");
+    let (dir, name) = key.rsplit_once('/').map_or(("", key), |(d, n)| (d, n));
+    let name = match name.rsplit_once('.') {
+        Some((stem, ext)) if !stem.is_empty() => format!("{stem} (conflict {stamp}).{ext}"),
+        _ => format!("{name} (conflict {stamp})"),
+    };
+    if dir.is_empty() {
+        name
+    } else {
+        format!("{dir}/{name}")
+    }
+}
+
+fn open_store(config: &NativeSyncConfig) -> Result<S3Store, String> {
+    super::sync::validate_profile_name(&config.profile)?;
+    let secret = secrets::get_secret(&secret_key(&config.profile))?
+        .ok_or_else(|| format!("No S3 secret key stored for profile {}", config.profile))?;
+    S3Store::new(config, secret)
+}
+
+fn prepare_root(local_path: &str) -> Result<PathBuf, String> {
+    let root = PathBuf::from(local_path);
+    fs::create_dir_all(&root).map_err(|e| format!("Failed to create local directory: {e}"))?;
+    Ok(root)
+}
+
+fn state_file(profile: &str) -> String {
+    format!("native_sync_{profile}.json")
+}
+
+fn secret_key(profile: &str) -> String {
+    format!("native_sync/{profile}/secret_access_key")
+}
+
+/// Marks a profile as syncing for as long as it is alive.
+struct ProfileLock(String);
+
+impl ProfileLock {
+    fn acquire(profile: &str) -> Result<Self, String> {
+        let mut running = RUNNING_PROFILES
+            .lock()
+            .unwrap_or_else(std::sync::PoisonError::into_inner);
+        if !running.insert(profile.to_string()) {
+            return Err(format!("Profile {profile} is already syncing"));
+        }
+        Ok(Self(profile.to_string()))
+    }
+}
+
+impl Drop for ProfileLock {
+    fn drop(&mut self) {
+        RUNNING_PROFILES
+            .lock()
+            .unwrap_or_else(std::sync::PoisonError::into_inner)
+            .remove(&self.0);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+
+    /// In-process stand-in for an S3 bucket, with ETags computed the way S3
+    /// does: the MD5 of the body, or for multipart uploads the MD5 of the
+    /// part digests followed by the part count.
+    #[derive(Default)]
+    struct MemoryStore {
+        objects: Mutex<BTreeMap<String, (Vec<u8>, String)>>,
+        uploads: Mutex<HashMap<String, BTreeMap<u32, Vec<u8>>>>,
+        fail_part: Mutex<Option<u32>>,
+        /// Drop the connection after this many bytes of a download.
+        fail_download_after: Mutex<Option<usize>>,
+        /// Offsets downloads were requested from.
+        get_offsets: Mutex<Vec<u64>>,
+    }
+
+    /// Hands out a body in small chunks, failing where the store says the
+    /// connection drops.
+    struct MemoryBody {
+        data: Vec<u8>,
+        offset: u64,
+        position: usize,
+        fail_after: Option<usize>,
+    }
+
+    impl ObjectBody for MemoryBody {
+        fn offset(&self) -> u64 {
+            self.offset
+        }
+
+        async fn next_chunk(&mut self) -> Result<Option<Vec<u8>>, String> {
+            if self.fail_after.is_some_and(|limit| self.position >= limit) {
+                return Err("connection reset".to_string());
+            }
+            let end = self.data.len().min(self.position + 1000);
+            let end = self.fail_after.map_or(end, |limit| end.min(limit));
+            if self.position == end {
+                return Ok(None);
+            }
+            let chunk = self.data[self.position..end].to_vec();
+            self.position = end;
+            Ok(Some(chunk))
+        }
+    }
+
+    impl MemoryStore {
+        fn insert(&self, key: &str, body: &[u8]) {
+            let etag = format!("\"{}\"", hex::encode(Md5::digest(body)));
+            self.objects
+                .lock()
+                .unwrap()
+                .insert(key.to_string(), (body.to_vec(), etag));
+        }
+
+        fn body(&self, key: &str) -> Option<Vec<u8>> {
+            self.objects
+                .lock()
+                .unwrap()
+                .get(key)
+                .map(|(b, _)| b.clone())
+        }
+    }
+
+    impl ObjectStore for MemoryStore {
+        type Body = MemoryBody;
+
+        async fn list(&self) -> Result<Vec<RemoteObject>, String> {
+            Ok(self
+                .objects
+                .lock()
+                .unwrap()
+                .iter()
+                .map(|(key, (body, etag))| RemoteObject {
+                    key: key.clone(),
+                    etag: etag.clone(),
+                    size: body.len() as u64,
+                })
+                .collect())
+        }
+
+        async fn get(&self, key: &str, etag: &str, offset: u64) -> Result<MemoryBody, String> {
+            let (body, current) = self
+                .objects
+                .lock()
+                .unwrap()
+                .get(key)
+                .cloned()
+                .ok_or("NoSuchKey")?;
+            if offset > 0 && current != etag {
+                return Err("PreconditionFailed".to_string());
+            }
+            self.get_offsets.lock().unwrap().push(offset);
+
+            let start = usize::try_from(offset).unwrap();
+            Ok(MemoryBody {
+                data: body[start..].to_vec(),
+                offset,
+                position: 0,
+                fail_after: *self.fail_download_after.lock().unwrap(),
+            })
+        }
+
+        async fn put(&self, key: &str, body: Vec<u8>) -> Result<String, String> {
+            self.insert(key, &body);
+            Ok(self.objects.lock().unwrap()[key].1.clone())
+        }
+
+        async fn delete(&self, key: &str) -> Result<(), String> {
+            self.objects.lock().unwrap().remove(key);
+            Ok(())
+        }
+
+        async fn create_multipart(&self, key: &str) -> Result<String, String> {
+            let upload_id = format!("{key}-upload");
+            self.uploads
+                .lock()
+                .unwrap()
+                .insert(upload_id.clone(), BTreeMap::new());
+            Ok(upload_id)
+        }
+
+        async fn upload_part(
+            &self,
+            _key: &str,
+            upload_id: &str,
+            part_number: u32,
+            body: Vec<u8>,
+        ) -> Result<String, String> {
+            if *self.fail_part.lock().unwrap() == Some(part_number) {
+                return Err("connection reset".to_string());
+            }
+            let etag = hex::encode(Md5::digest(&body));
+            self.uploads
+                .lock()
+                .unwrap()
+                .get_mut(upload_id)
+                .ok_or("NoSuchUpload")?
+                .insert(part_number, body);
+            Ok(etag)
+        }
+
+        async fn list_parts(
+            &self,
+            _key: &str,
+            upload_id: &str,
+        ) -> Result<Vec<CompletedPart>, String> {
+            let uploads = self.uploads.lock().unwrap();
+            let parts = uploads.get(upload_id).ok_or("NoSuchUpload")?;
+            Ok(parts
+                .iter()
+                .map(|(part_number, body)| CompletedPart {
+                    part_number: *part_number,
+                    etag: hex::encode(Md5::digest(body)),
+                })
+                .collect())
+        }
+
+        async fn complete_multipart(
+            &self,
+            key: &str,
+            upload_id: &str,
+            parts: &[CompletedPart],
+        ) -> Result<String, String> {
+            let stored = self
+                .uploads
+                .lock()
+                .unwrap()
+                .remove(upload_id)
+                .ok_or("NoSuchUpload")?;
+            let mut body = Vec::new();
+            let mut digests = Vec::new();
+            for part in parts {
+                let data = &stored[&part.part_number];
+                body.extend_from_slice(data);
+                digests.extend_from_slice(&Md5::digest(data));
+            }
+            let etag = format!("\"{}-{}\"", hex::encode(Md5::digest(&digests)), parts.len());
+            self.objects
+                .lock()
+                .unwrap()
+                .insert(key.to_string(), (body, etag.clone()));
+            Ok(etag)
+        }
+
+        async fn abort_multipart(&self, _key: &str, upload_id: &str) -> Result<(), String> {
+            self.uploads.lock().unwrap().remove(upload_id);
+            Ok(())
+        }
+    }
+
+    fn temp_root(name: &str) -> PathBuf {
+        let root = std::env::temp_dir().join(format!(
+            "botapp-native-{name}-{}",
+            uuid::Uuid::new_v4().simple()
+        ));
+        fs::create_dir_all(&root).unwrap();
+        root
+    }
+
+    fn no_persist(_: &SyncState) -> Result<(), String> {
+        Ok(())
+    }
+
+    fn local(hash: &str) -> LocalFile {
+        LocalFile {
+            size: 1,
+            modified: 0,
+            hash: hash.to_string(),
+        }
+    }
+
+    fn object(key: &str, etag: &str) -> RemoteObject {
+        RemoteObject {
+            key: key.to_string(),
+            etag: etag.to_string(),
+            size: 1,
+        }
+    }
+
+    fn base(hash: &str, etag: &str) -> FileState {
+        FileState {
+            size: 1,
+            modified: 0,
+            hash: hash.to_string(),
+            etag: etag.to_string(),
+        }
+    }
+
+    #[test]
+    fn test_plan_three_way() {
+        let local = BTreeMap::from([
+            ("new-local".to_string(), local("a")),
+            ("edited-local".to_string(), local("b2")),
+            ("edited-both".to_string(), local("c2")),
+            ("deleted-remote".to_string(), local("d")),
+            ("unchanged".to_string(), local("e")),
+        ]);
+        let remote = BTreeMap::from([
+            ("new-remote".to_string(), object("new-remote", "f")),
+            ("edited-local".to_string(), object("edited-local", "b")),
+            ("edited-both".to_string(), object("edited-both", "c3")),
+            ("deleted-local".to_string(), object("deleted-local", "g")),
+            ("unchanged".to_string(), object("unchanged", "e")),
+        ]);
+        let state = BTreeMap::from([
+            ("edited-local".to_string(), base("b", "b")),
+            ("edited-both".to_string(), base("c", "c")),
+            ("deleted-remote".to_string(), base("d", "d")),
+            ("deleted-local".to_string(), base("g", "g")),
+            ("unchanged".to_string(), base("e", "e")),
+            ("gone".to_string(), base("h", "h")),
+        ]);
+
+        let actions: BTreeMap<String, Action> = plan(&local, &remote, &state)
+            .into_iter()
+            .map(|p| (p.path, p.action))
+            .collect();
+
+        assert_eq!(actions["new-local"], Action::Upload);
+        assert_eq!(actions["new-remote"], Action::Download);
+        assert_eq!(actions["edited-local"], Action::Upload);
+        assert_eq!(actions["edited-both"], Action::Conflict);
+        assert_eq!(actions["deleted-remote"], Action::DeleteLocal);
+        assert_eq!(actions["deleted-local"], Action::DeleteRemote);
+        assert_eq!(actions["gone"], Action::Forget);
+        assert!(!actions.contains_key("unchanged"));
+    }
+
+    #[test]
+    fn test_plan_adopts_identical_files() {
+        let local = BTreeMap::from([("same".to_string(), local("abc"))]);
+        let remote = BTreeMap::from([("same".to_string(), object("same", "\"abc\""))]);
+        let actions = plan(&local, &remote, &BTreeMap::new());
+        assert_eq!(actions[0].action, Action::Record);
+    }
+
+    #[test]
+    fn test_local_path_rejects_escaping_keys() {
+        let root = Path::new("/sync");
+        assert_eq!(
+            local_path(root, "docs/a.txt").unwrap(),
+            PathBuf::from("/sync/docs/a.txt")
+        );
+        assert!(local_path(root, "../etc/passwd").is_err());
+        assert!(local_path(root, "/etc/passwd").is_err());
+        assert!(local_path(root, "docs/").is_err());
+        assert!(local_path(root, "a\\b").is_err());
+    }
+
+    #[test]
+    fn test_conflict_name() {
+        let now = chrono::DateTime::parse_from_rfc3339("2026-10-18T10:15:00Z").unwrap();
+        assert_eq!(
+            conflict_name("docs/report.pdf", &now),
+            "docs/report (conflict 2026-10-18 101500).pdf"
+        );
+        assert_eq!(
+            conflict_name("Makefile", &now),
+            "Makefile (conflict 2026-10-18 101500)"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_sync_round_trip() {
+        let root = temp_root("round-trip");
+        let store = MemoryStore::default();
+        let mut state = SyncState::default();
+
+        fs::create_dir_all(root.join("docs")).unwrap();
+        fs::write(root.join("docs/local.txt"), b"local").unwrap();
+        store.insert("remote.txt", b"remote");
+
+        let report = run_sync(&store, &root, &mut state, &no_persist)
+            .await
+            .unwrap();
+        assert_eq!((report.uploaded, report.downloaded), (1, 1));
+        assert_eq!(store.body("docs/local.txt").unwrap(), b"local");
+        assert_eq!(fs::read(root.join("remote.txt")).unwrap(), b"remote");
+
+        // Nothing changed, so the next run does nothing.
+        let report = run_sync(&store, &root, &mut state, &no_persist)
+            .await
+            .unwrap();
+        assert_eq!((report.uploaded, report.downloaded), (0, 0));
+
+        fs::remove_file(root.join("remote.txt")).unwrap();
+        store.delete("docs/local.txt").await.unwrap();
+        let report = run_sync(&store, &root, &mut state, &no_persist)
+            .await
+            .unwrap();
+        assert_eq!((report.deleted_local, report.deleted_remote), (1, 1));
+        assert!(store.body("remote.txt").is_none());
+        assert!(!root.join("docs/local.txt").exists());
+        assert!(state.files.is_empty());
+
+        fs::remove_dir_all(root).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_conflict_keeps_both_versions() {
+        let root = temp_root("conflict");
+        let store = MemoryStore::default();
+        let mut state = SyncState::default();
+
+        fs::write(root.join("notes.txt"), b"mine").unwrap();
+        store.insert("notes.txt", b"theirs");
+
+        let report = run_sync(&store, &root, &mut state, &no_persist)
+            .await
+            .unwrap();
+        assert_eq!(report.conflicts, vec!["notes.txt".to_string()]);
+        assert_eq!(fs::read(root.join("notes.txt")).unwrap(), b"theirs");
+
+        let objects = store.list().await.unwrap();
+        let copy = objects
+            .iter()
+            .find(|o| o.key.starts_with("notes (conflict "))
+            .unwrap();
+        assert_eq!(store.body(&copy.key).unwrap(), b"mine");
+        assert_eq!(fs::read(root.join(&copy.key)).unwrap(), b"mine");
+
+        fs::remove_dir_all(root).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_multipart_upload_resumes() {
+        let root = temp_root("multipart");
+        let store = MemoryStore::default();
+        let mut state = SyncState::default();
+
+        let size = usize::try_from(MULTIPART_THRESHOLD + MIN_PART_SIZE / 2).unwrap();
+        let content: Vec<u8> = (0..size).map(|i| (i % 251) as u8).collect();
+        fs::write(root.join("big.bin"), &content).unwrap();
+
+        *store.fail_part.lock().unwrap() = Some(3);
+        let report = run_sync(&store, &root, &mut state, &no_persist)
+            .await
+            .unwrap();
+        assert_eq!(report.errors.len(), 1);
+        assert!(state.uploads.contains_key("big.bin"));
+
+        *store.fail_part.lock().unwrap() = None;
+        let report = run_sync(&store, &root, &mut state, &no_persist)
+            .await
+            .unwrap();
+        assert_eq!(report.uploaded, 1);
+        assert!(state.uploads.is_empty());
+        assert_eq!(store.body("big.bin").unwrap(), content);
+        assert!(state.files["big.bin"].etag.ends_with("-3\""));
+
+        fs::remove_dir_all(root).unwrap();
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_unreadable_synced_file_is_not_deleted() {
+        let root = temp_root("unreadable");
+        let store = MemoryStore::default();
+        let mut state = SyncState::default();
+
+        fs::write(root.join("a.txt"), "a").unwrap();
+        run_sync(&store, &root, &mut state, &no_persist)
+            .await
+            .unwrap();
+        assert!(state.files.contains_key("a.txt"));
+
+        // A socket cannot be read like a file, even by root.
+        fs::remove_file(root.join("a.txt")).unwrap();
+        let _socket = std::os::unix::net::UnixListener::bind(root.join("a.txt")).unwrap();
+        assert!(run_sync(&store, &root, &mut state, &no_persist)
+            .await
+            .is_err());
+        assert!(store.objects.lock().unwrap().contains_key("a.txt"));
+        assert!(state.files.contains_key("a.txt"));
+
+        fs::remove_dir_all(root).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_download_resumes() {
+        let root = temp_root("download");
+        let store = MemoryStore::default();
+        let mut state = SyncState::default();
+
+        let content: Vec<u8> = (0..10_000).map(|i| (i % 251) as u8).collect();
+        store.insert("big.bin", &content);
+
+        *store.fail_download_after.lock().unwrap() = Some(4000);
+        let report = run_sync(&store, &root, &mut state, &no_persist)
+            .await
+            .unwrap();
+        assert_eq!(report.errors.len(), 1);
+        assert!(state.downloads.contains_key("big.bin"));
+        assert!(!root.join("big.bin").exists());
+        let partial = root.join(format!("big.bin{PARTIAL_SUFFIX}"));
+        assert_eq!(fs::metadata(&partial).unwrap().len(), 4000);
+
+        *store.fail_download_after.lock().unwrap() = None;
+        let report = run_sync(&store, &root, &mut state, &no_persist)
+            .await
+            .unwrap();
+        assert_eq!(report.downloaded, 1);
+        assert_eq!(report.bytes_transferred, 6000);
+        assert_eq!(*store.get_offsets.lock().unwrap(), [0, 4000]);
+        assert_eq!(fs::read(root.join("big.bin")).unwrap(), content);
+        assert!(!partial.exists());
+        assert!(state.downloads.is_empty());
+        assert_eq!(
+            state.files["big.bin"].hash,
+            hex::encode(Md5::digest(&content))
+        );
+
+        fs::remove_dir_all(root).unwrap();
+    }
+}
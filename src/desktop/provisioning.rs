@@ -0,0 +1,406 @@
+use super::command_path::{self, TrustedLocation};
+use super::safe_command::SafeCommand;
+use super::{rc, storage};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::ffi::OsStr;
+use std::fs;
+use std::io::{Cursor, Read};
+use std::path::{Path, PathBuf};
+
+const INSTALL_FILE: &str = "rclone_install.json";
+const SOURCE_FILE: &str = "rclone_source.json";
+const INSTALL_DIR: &str = "rclone";
+const MAX_ARCHIVE_SIZE: u64 = 256 * 1024 * 1024;
+
+/// Path of an rclone archive that overrides the configured install source,
+/// for offline deployments that ship it next to the app. Its SHA-256 must be
+/// given in [`ARCHIVE_SHA256_ENV`].
+pub const ARCHIVE_ENV: &str = "BOTAPP_RCLONE_ARCHIVE";
+pub const ARCHIVE_SHA256_ENV: &str = "BOTAPP_RCLONE_SHA256";
+
+/// Download URL and SHA-256 of the rclone archive for the target platform,
+/// pinned by the packager when building the app.
+const PINNED_URL: Option<&str> = option_env!("BOTAPP_RCLONE_URL");
+const PINNED_SHA256: Option<&str> = option_env!("BOTAPP_RCLONE_SHA256");
+
+/// Where `install_rclone` gets the official rclone zip archive from. Every
+/// source comes with the SHA-256 the archive must have.
+///
+/// Sources are only taken from the environment, the app config written by
+/// deployment tooling, or the build, never from the webview, so a page
+/// cannot pick both an archive and the checksum it is verified against.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum InstallSource {
+    File { path: String, sha256: String },
+    Url { url: String, sha256: String },
+}
+
+/// The rclone binary installed by the app. `sha256` is the hash of the
+/// archive it came from and `binary_sha256` the hash every run is checked
+/// against.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RcloneInstall {
+    pub path: String,
+    pub version: String,
+    pub sha256: String,
+    #[serde(default)]
+    pub binary_sha256: Option<String>,
+    pub source: InstallSource,
+    pub installed_at: String,
+}
+
+/// The program used for rclone commands: the managed binary when one is
+/// installed, otherwise `rclone` from `PATH`.
+#[must_use]
+pub fn rclone_program() -> String {
+    get_rclone_install()
+        .map(|install| install.path)
+        .filter(|path| Path::new(path).is_file())
+        .unwrap_or_else(|| "rclone".to_string())
+}
+
+/// Directory holding the managed rclone binary, trusted by
+/// [`command_path::resolve`].
+///
+/// # Errors
+/// Returns an error if the data directory cannot be determined.
+pub fn managed_dir() -> Result<PathBuf, String> {
+    Ok(storage::app_data_dir()?.join(INSTALL_DIR))
+}
+
+/// The managed rclone binary and the SHA-256 it was installed with.
+#[must_use]
+pub fn managed_location() -> Option<TrustedLocation> {
+    get_rclone_install().map(|install| TrustedLocation {
+        path: PathBuf::from(install.path),
+        sha256: install.binary_sha256,
+    })
+}
+
+/// The managed rclone installation, if any.
+#[tauri::command]
+#[must_use]
+pub fn get_rclone_install() -> Option<RcloneInstall> {
+    storage::load_json(INSTALL_FILE)
+}
+
+/// The source `install_rclone` uses: [`ARCHIVE_ENV`] first, then
+/// `rclone_source.json` in the app data directory, then the archive pinned
+/// in the build. `None` when there is none of these.
+#[tauri::command]
+#[must_use]
+pub fn get_rclone_source() -> Option<InstallSource> {
+    if let (Ok(path), Ok(sha256)) = (
+        std::env::var(ARCHIVE_ENV),
+        std::env::var(ARCHIVE_SHA256_ENV),
+    ) {
+        return Some(InstallSource::File { path, sha256 });
+    }
+
+    storage::load_json::<Option<InstallSource>>(SOURCE_FILE).or_else(|| {
+        Some(InstallSource::Url {
+            url: PINNED_URL?.to_string(),
+            sha256: PINNED_SHA256?.to_string(),
+        })
+    })
+}
+
+/// Install rclone into the app data directory from the configured source.
+/// The archive is rejected unless its SHA-256 matches.
+///
+/// # Errors
+/// Returns an error if no source is configured, the archive cannot be read,
+/// fails verification, does not contain an rclone binary, or the binary
+/// does not run.
+#[tauri::command]
+pub async fn install_rclone() -> Result<RcloneInstall, String> {
+    let source =
+        get_rclone_source().ok_or_else(|| "No rclone install source is configured".to_string())?;
+    source.validate()?;
+
+    let (archive, expected) = match &source {
+        InstallSource::File { path, sha256 } => {
+            (read_archive_file(Path::new(path))?, sha256.clone())
+        }
+        InstallSource::Url { url, sha256 } => (download_archive(url).await?, sha256.clone()),
+    };
+
+    tokio::task::spawn_blocking(move || install_archive(&archive, &expected, source))
+        .await
+        .map_err(|e| format!("rclone installation failed: {e}"))?
+}
+
+/// Remove the managed rclone binary, falling back to rclone from `PATH`.
+///
+/// # Errors
+/// Returns an error if the binary or the install record cannot be removed.
+#[tauri::command]
+pub fn uninstall_rclone() -> Result<(), String> {
+    let dir = managed_dir()?;
+    if dir.exists() {
+        fs::remove_dir_all(&dir).map_err(|e| format!("Failed to remove rclone: {e}"))?;
+    }
+    let record = storage::app_data_dir()?.join(INSTALL_FILE);
+    if record.exists() {
+        fs::remove_file(record).map_err(|e| format!("Failed to remove rclone: {e}"))?;
+    }
+    command_path::invalidate();
+    rc::invalidate_daemon();
+    Ok(())
+}
+
+impl InstallSource {
+    fn validate(&self) -> Result<(), String> {
+        match self {
+            Self::File { path, sha256 } => {
+                if !Path::new(path).is_absolute() {
+                    return Err(format!("Archive path must be absolute: {path}"));
+                }
+                validate_sha256(sha256)
+            }
+            Self::Url { url, sha256 } => {
+                if !url.starts_with("https://") {
+                    return Err(format!("Archive URL must use https: {url}"));
+                }
+                validate_sha256(sha256)
+            }
+        }
+    }
+}
+
+fn validate_sha256(sha256: &str) -> Result<(), String> {
+    if sha256.len() == 64 && sha256.chars().all(|c| c.is_ascii_hexdigit()) {
+        Ok(())
+    } else {
+        Err(format!("Invalid SHA-256 checksum: {sha256}"))
+    }
+}
+
+/// Verify, extract and record an archive. The binary is unpacked into a
+/// staging directory and only replaces the current one after it ran.
+fn install_archive(
+    archive: &[u8],
+    expected: &str,
+    source: InstallSource,
+) -> Result<RcloneInstall, String> {
+    let sha256 = verify_checksum(archive, expected)?;
+    let binary = extract_binary(archive)?;
+    let binary_sha256 = hex::encode(Sha256::digest(&binary));
+
+    let dir = managed_dir()?;
+    let staging = dir.join(format!("staging-{}", uuid::Uuid::new_v4().simple()));
+    fs::create_dir_all(&staging)
+        .map_err(|e| format!("Failed to create {}: {e}", staging.display()))?;
+
+    let result = write_executable(&staging.join(binary_name()), &binary)
+        .and_then(|staged| rclone_version(&staged).map(|version| (staged, version)))
+        .and_then(|(staged, version)| {
+            let path = dir.join(binary_name());
+            fs::rename(&staged, &path).map_err(|e| format!("Failed to install rclone: {e}"))?;
+            Ok((path, version))
+        });
+    let _ = fs::remove_dir_all(&staging);
+    let (path, version) = result?;
+
+    let install = RcloneInstall {
+        path: path.to_string_lossy().to_string(),
+        version,
+        sha256,
+        binary_sha256: Some(binary_sha256),
+        source,
+        installed_at: chrono::Utc::now().to_rfc3339(),
+    };
+    storage::save_json(INSTALL_FILE, &install)?;
+    command_path::invalidate();
+    rc::invalidate_daemon();
+
+    log::info!("Installed rclone {} at {}", install.version, install.path);
+    Ok(install)
+}
+
+fn verify_checksum(archive: &[u8], expected: &str) -> Result<String, String> {
+    validate_sha256(expected)?;
+    let actual = hex::encode(Sha256::digest(archive));
+    if actual.eq_ignore_ascii_case(expected) {
+        Ok(actual)
+    } else {
+        Err(format!(
+            "rclone archive checksum mismatch: expected {expected}, got {actual}"
+        ))
+    }
+}
+
+/// Read the single rclone executable out of a release archive, which keeps
+/// it in a versioned top-level folder.
+fn extract_binary(archive: &[u8]) -> Result<Vec<u8>, String> {
+    let mut zip = zip::ZipArchive::new(Cursor::new(archive))
+        .map_err(|e| format!("Invalid rclone archive: {e}"))?;
+
+    let mut found = None;
+    for index in 0..zip.len() {
+        let entry = zip
+            .by_index(index)
+            .map_err(|e| format!("Invalid rclone archive: {e}"))?;
+        let is_binary = entry.is_file()
+            && Path::new(entry.name()).file_name() == Some(OsStr::new(binary_name()));
+        if is_binary {
+            if found.is_some() {
+                return Err("rclone archive contains more than one binary".to_string());
+            }
+            found = Some(index);
+        }
+    }
+    let index = found.ok_or_else(|| "rclone archive does not contain rclone".to_string())?;
+
+    let entry = zip
+        .by_index(index)
+        .map_err(|e| format!("Invalid rclone archive: {e}"))?;
+    let mut binary = Vec::new();
+    entry
+        .take(MAX_ARCHIVE_SIZE)
+        .read_to_end(&mut binary)
+        .map_err(|e| format!("Failed to extract rclone: {e}"))?;
+    Ok(binary)
+}
+
+fn write_executable(path: &Path, content: &[u8]) -> Result<PathBuf, String> {
+    fs::write(path, content).map_err(|e| format!("Failed to write rclone: {e}"))?;
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        fs::set_permissions(path, fs::Permissions::from_mode(0o755))
+            .map_err(|e| format!("Failed to make rclone executable: {e}"))?;
+    }
+    Ok(path.to_path_buf())
+}
+
+/// Run `rclone version` and return the version from its first line, such as
+/// `v1.68.1`.
+fn rclone_version(path: &Path) -> Result<String, String> {
+    let output = SafeCommand::new(&path.to_string_lossy())
+        .and_then(|c| c.arg("version"))
+        .map_err(|e| format!("Failed to build rclone command: {e}"))?
+        .output()
+        .map_err(|e| format!("Installed rclone does not run: {e}"))?;
+    if !output.status.success() {
+        return Err("Installed rclone does not run".to_string());
+    }
+
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    stdout
+        .lines()
+        .next()
+        .and_then(|line| line.strip_prefix("rclone "))
+        .map(|version| version.trim().to_string())
+        .ok_or_else(|| "Unexpected rclone version output".to_string())
+}
+
+fn read_archive_file(path: &Path) -> Result<Vec<u8>, String> {
+    let size = fs::metadata(path)
+        .map_err(|e| format!("Failed to read {}: {e}", path.display()))?
+        .len();
+    if size > MAX_ARCHIVE_SIZE {
+        return Err(format!(
+            "{} is too large for an rclone archive",
+            path.display()
+        ));
+    }
+    fs::read(path).map_err(|e| format!("Failed to read {}: {e}", path.display()))
+}
+
+/// Download an archive, giving up as soon as it grows past
+/// [`MAX_ARCHIVE_SIZE`], whether or not the server announced its length.
+async fn download_archive(url: &str) -> Result<Vec<u8>, String> {
+    let too_large = || "rclone download is too large".to_string();
+    let mut response = reqwest::get(url)
+        .await
+        .and_then(reqwest::Response::error_for_status)
+        .map_err(|e| format!("Failed to download rclone: {e}"))?;
+    if response
+        .content_length()
+        .is_some_and(|len| len > MAX_ARCHIVE_SIZE)
+    {
+        return Err(too_large());
+    }
+
+    let mut archive = Vec::new();
+    while let Some(chunk) = response
+        .chunk()
+        .await
+        .map_err(|e| format!("Failed to download rclone: {e}"))?
+    {
+        if (archive.len() + chunk.len()) as u64 > MAX_ARCHIVE_SIZE {
+            return Err(too_large());
+        }
+        archive.extend_from_slice(&chunk);
+    }
+    Ok(archive)
+}
+
+fn binary_name() -> &'static str {
+    if cfg!(windows) {
+        "rclone.exe"
+    } else {
+        "rclone"
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+    use zip::write::SimpleFileOptions;
+
+    fn archive(entries: &[(&str, &[u8])]) -> Vec<u8> {
+        let mut zip = zip::ZipWriter::new(Cursor::new(Vec::new()));
+        let options =
+            SimpleFileOptions::default().compression_method(zip::CompressionMethod::Stored);
+        for (name, content) in entries {
+            zip.start_file(*name, options).unwrap();
+            zip.write_all(content).unwrap();
+        }
+        zip.finish().unwrap().into_inner()
+    }
+
+    #[test]
+    fn test_extract_binary() {
+        let binary = format!("rclone-v1.68.1-linux-amd64/{}", binary_name());
+        let zip = archive(&[
+            ("rclone-v1.68.1-linux-amd64/README.txt", b"readme"),
+            (&binary, b"\x7fELF"),
+        ]);
+        assert_eq!(extract_binary(&zip).unwrap(), b"\x7fELF");
+
+        let zip = archive(&[("rclone-v1.68.1-linux-amd64/README.txt", b"readme")]);
+        assert!(extract_binary(&zip).is_err());
+        assert!(extract_binary(b"not a zip").is_err());
+    }
+
+    #[test]
+    fn test_verify_checksum() {
+        let sha256 = "2cf24dba5fb0a30e26e83b2ac5b9e29e1b161e5c1fa7425e73043362938b9824";
+        assert_eq!(verify_checksum(b"hello", sha256).unwrap(), sha256);
+        assert!(verify_checksum(b"hello", &sha256.to_uppercase()).is_ok());
+        assert!(verify_checksum(b"hello!", sha256).is_err());
+        assert!(verify_checksum(b"hello", "abc").is_err());
+    }
+
+    #[test]
+    fn test_source_validation() {
+        let sha256 = "0".repeat(64);
+        assert!(InstallSource::Url {
+            url: "http://downloads.rclone.org/rclone.zip".to_string(),
+            sha256: sha256.clone(),
+        }
+        .validate()
+        .is_err());
+        assert!(InstallSource::File {
+            path: "relative/rclone.zip".to_string(),
+            sha256,
+        }
+        .validate()
+        .is_err());
+    }
+}
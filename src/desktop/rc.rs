@@ -0,0 +1,292 @@
+use super::rclone::rclone_command;
+use super::safe_command::SafeProcess;
+use super::sandbox::Sandbox;
+use serde::Deserialize;
+use serde_json::{json, Value};
+use std::collections::VecDeque;
+use std::io::{BufRead, BufReader, Read};
+use std::net::{Ipv4Addr, TcpListener};
+use std::process::Stdio;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+static RCD: Mutex<Option<Daemon>> = Mutex::new(None);
+
+/// Log lines the daemon wrote to stderr and nobody has taken yet.
+static RCD_LOG: Mutex<VecDeque<String>> = Mutex::new(VecDeque::new());
+
+const RC_USER: &str = "botapp";
+const STARTUP_TIMEOUT: Duration = Duration::from_secs(10);
+const STARTUP_POLL_INTERVAL: Duration = Duration::from_millis(200);
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(30);
+const MAX_LOG_LINES: usize = 1000;
+
+/// The `rclone rcd` daemon shared by every sync using the remote-control
+/// backend. `stale` is set when credentials change, since the daemon only
+/// sees the environment it was started with.
+struct Daemon {
+    child: SafeProcess,
+    client: RcClient,
+    stale: bool,
+}
+
+/// Authenticated client for the remote-control API of an `rclone rcd`
+/// listening on localhost.
+#[derive(Clone)]
+pub struct RcClient {
+    http: reqwest::Client,
+    url: String,
+    pass: String,
+}
+
+/// Result of `job/status` for an asynchronous job.
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct JobStatus {
+    #[serde(default)]
+    pub finished: bool,
+    #[serde(default)]
+    pub success: bool,
+    #[serde(default)]
+    pub error: String,
+}
+
+/// Transfer counters of a job from `core/stats`.
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct JobStats {
+    #[serde(default)]
+    pub bytes: u64,
+    #[serde(default)]
+    pub transfers: u64,
+    #[serde(default)]
+    pub errors: u64,
+    #[serde(default, rename = "lastError")]
+    pub last_error: Option<String>,
+    #[serde(default)]
+    pub transferring: Vec<Transfer>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct Transfer {
+    pub name: String,
+}
+
+impl RcClient {
+    /// Call an rc method with JSON parameters.
+    ///
+    /// # Errors
+    /// Returns the error reported by rclone, or a transport error.
+    pub async fn call(&self, method: &str, params: &Value) -> Result<Value, String> {
+        let response = self
+            .http
+            .post(format!("{}/{method}", self.url))
+            .basic_auth(RC_USER, Some(&self.pass))
+            .json(params)
+            .send()
+            .await
+            .map_err(|e| format!("rclone rc {method} failed: {e}"))?;
+
+        let status = response.status();
+        let body: Value = response
+            .json()
+            .await
+            .map_err(|e| format!("Invalid response from rclone rc {method}: {e}"))?;
+
+        if !status.is_success() {
+            let error = body
+                .get("error")
+                .and_then(Value::as_str)
+                .unwrap_or_else(|| status.as_str());
+            return Err(format!("rclone rc {method} failed: {error}"));
+        }
+        Ok(body)
+    }
+
+    /// Start `method` as an asynchronous job and return its id.
+    ///
+    /// # Errors
+    /// Returns an error if rclone rejects the job.
+    pub async fn start_job(&self, method: &str, mut params: Value) -> Result<u64, String> {
+        params["_async"] = Value::Bool(true);
+        self.call(method, &params)
+            .await?
+            .get("jobid")
+            .and_then(Value::as_u64)
+            .ok_or_else(|| format!("rclone rc {method} returned no job id"))
+    }
+
+    /// # Errors
+    /// Returns an error if the job is unknown or rclone cannot be reached.
+    pub async fn job_status(&self, job_id: u64) -> Result<JobStatus, String> {
+        let body = self.call("job/status", &json!({ "jobid": job_id })).await?;
+        serde_json::from_value(body).map_err(|e| format!("Invalid job status: {e}"))
+    }
+
+    /// # Errors
+    /// Returns an error if rclone cannot be reached.
+    pub async fn job_stats(&self, job_id: u64) -> Result<JobStats, String> {
+        let body = self
+            .call("core/stats", &json!({ "group": format!("job/{job_id}") }))
+            .await?;
+        serde_json::from_value(body).map_err(|e| format!("Invalid job stats: {e}"))
+    }
+
+    /// Cancel a job. rclone stops starting new transfers and the job
+    /// finishes with an error shortly after.
+    ///
+    /// # Errors
+    /// Returns an error if the job is unknown or rclone cannot be reached.
+    pub async fn stop_job(&self, job_id: u64) -> Result<(), String> {
+        self.call("job/stop", &json!({ "jobid": job_id }))
+            .await
+            .map(|_| ())
+    }
+
+    /// Names of the remotes in the rclone config, without the trailing `:`.
+    ///
+    /// # Errors
+    /// Returns an error if rclone cannot be reached.
+    pub async fn list_remotes(&self) -> Result<Vec<String>, String> {
+        let body = self.call("config/listremotes", &json!({})).await?;
+        Ok(body
+            .get("remotes")
+            .and_then(Value::as_array)
+            .map(|remotes| {
+                remotes
+                    .iter()
+                    .filter_map(Value::as_str)
+                    .map(str::to_string)
+                    .collect()
+            })
+            .unwrap_or_default())
+    }
+}
+
+/// Return a client for the running daemon, starting a new `rclone rcd` on a
+/// free localhost port when there is none, it exited, or it is stale. The rc
+/// password is generated per daemon and passed through the environment.
+///
+/// # Errors
+/// Returns an error if rclone cannot be started or does not answer in time.
+pub async fn ensure_daemon() -> Result<RcClient, String> {
+    {
+        let mut rcd_guard = RCD
+            .lock()
+            .unwrap_or_else(std::sync::PoisonError::into_inner);
+        if let Some(daemon) = rcd_guard.as_mut() {
+            if !daemon.stale && matches!(daemon.child.try_wait(), Ok(None)) {
+                return Ok(daemon.client.clone());
+            }
+        }
+        if let Some(mut daemon) = rcd_guard.take() {
+            daemon.child.kill();
+        }
+    }
+
+    let port = free_port()?;
+    let pass = uuid::Uuid::new_v4().simple().to_string();
+    let mut child = rclone_command()?
+        .arg("rcd")
+        .and_then(|c| c.arg("--rc-addr"))
+        .and_then(|c| c.arg(&format!("127.0.0.1:{port}")))
+        .and_then(|c| c.arg("--use-json-log"))
+        .and_then(|c| c.env("RCLONE_RC_USER", RC_USER))
+        .and_then(|c| c.env("RCLONE_RC_PASS", &pass))
+        .map_err(|e| format!("Failed to build rclone command: {e}"))?
+        .stdout(Stdio::null())
+        .stderr(Stdio::piped())
+        .sandbox(Sandbox::RCLONE)
+        .spawn()
+        .map_err(|e| format!("Failed to start rclone rcd: {e}"))?;
+    if let Some(stderr) = child.take_stderr() {
+        std::thread::spawn(move || collect_log(stderr));
+    }
+
+    let client = RcClient {
+        http: reqwest::Client::builder()
+            .timeout(REQUEST_TIMEOUT)
+            .build()
+            .map_err(|e| format!("Failed to create HTTP client: {e}"))?,
+        url: format!("http://127.0.0.1:{port}"),
+        pass,
+    };
+
+    let deadline = Instant::now() + STARTUP_TIMEOUT;
+    loop {
+        if client.call("core/version", &json!({})).await.is_ok() {
+            break;
+        }
+        let exited = !matches!(child.try_wait(), Ok(None));
+        if exited || Instant::now() >= deadline {
+            child.kill();
+            return Err("rclone rcd did not start".to_string());
+        }
+        tokio::time::sleep(STARTUP_POLL_INTERVAL).await;
+    }
+
+    let mut rcd_guard = RCD
+        .lock()
+        .unwrap_or_else(std::sync::PoisonError::into_inner);
+    if let Some(mut previous) = rcd_guard.take() {
+        previous.child.kill();
+    }
+    *rcd_guard = Some(Daemon {
+        child,
+        client: client.clone(),
+        stale: false,
+    });
+
+    Ok(client)
+}
+
+/// Make the next `ensure_daemon` start a fresh daemon, so it picks up
+/// changed credentials. A running job keeps the current one until then.
+pub fn invalidate_daemon() {
+    let mut rcd_guard = RCD
+        .lock()
+        .unwrap_or_else(std::sync::PoisonError::into_inner);
+    if let Some(daemon) = rcd_guard.as_mut() {
+        daemon.stale = true;
+    }
+}
+
+/// Kill the daemon, cancelling whatever job it is running.
+pub fn shutdown_daemon() {
+    let daemon = RCD
+        .lock()
+        .unwrap_or_else(std::sync::PoisonError::into_inner)
+        .take();
+    if let Some(mut daemon) = daemon {
+        daemon.child.kill();
+    }
+}
+
+/// Take the lines the daemon logged since the last call. Only one sync runs
+/// at a time, so they belong to the current job.
+pub fn take_log() -> Vec<String> {
+    RCD_LOG
+        .lock()
+        .unwrap_or_else(std::sync::PoisonError::into_inner)
+        .drain(..)
+        .collect()
+}
+
+/// Keep the latest lines of the daemon's stderr until it closes.
+fn collect_log(stderr: impl Read) {
+    for line in BufReader::new(stderr).lines() {
+        let Ok(line) = line else { break };
+        let mut log = RCD_LOG
+            .lock()
+            .unwrap_or_else(std::sync::PoisonError::into_inner);
+        if log.len() == MAX_LOG_LINES {
+            log.pop_front();
+        }
+        log.push_back(line);
+    }
+}
+
+fn free_port() -> Result<u16, String> {
+    TcpListener::bind((Ipv4Addr::LOCALHOST, 0))
+        .and_then(|listener| listener.local_addr())
+        .map(|addr| addr.port())
+        .map_err(|e| format!("Failed to find a free port: {e}"))
+}
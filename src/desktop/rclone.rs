@@ -0,0 +1,456 @@
+use super::provisioning::rclone_program;
+use super::safe_command::SafeCommand;
+use super::sync::{SyncBackend, SyncMode};
+use super::{rc, secrets, storage};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeSet, HashMap};
+use std::process::Output;
+
+const REMOTE_SECRETS_FILE: &str = "remote_secrets.json";
+const CONFIG_PASS_KEY: &str = "rclone/config_pass";
+
+/// Oldest rclone whose `--use-json-log` output carries the stats every sync
+/// reads its progress from, and that has `check --combined`.
+pub const MIN_JSON_LOG_VERSION: RcloneVersion = RcloneVersion::new(1, 54, 0);
+/// First release with `rclone bisync` and its `--filters-file`.
+pub const MIN_BISYNC_VERSION: RcloneVersion = RcloneVersion::new(1, 58, 0);
+/// First release exposing bisync through the rc API as `sync/bisync`.
+pub const MIN_RC_BISYNC_VERSION: RcloneVersion = RcloneVersion::new(1, 64, 0);
+
+/// A release version of rclone. Pre-release and build suffixes such as
+/// `-DEV` or `-beta.7001` are ignored.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+pub struct RcloneVersion {
+    pub major: u32,
+    pub minor: u32,
+    pub patch: u32,
+}
+
+/// What the installed rclone can do. `supported` is false when it is too
+/// old for any sync, and `issues` explains every missing capability.
+/// `managed` is set when the binary is the one installed by the app.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RcloneCapabilities {
+    pub version: RcloneVersion,
+    pub version_line: String,
+    pub managed: bool,
+    pub supported: bool,
+    pub json_log: bool,
+    pub bisync: bool,
+    pub sync_modes: Vec<SyncMode>,
+    /// Backends able to run at least one of `sync_modes`; the remote-control
+    /// backend also needs `rc_bisync` for bisync.
+    pub backends: Vec<SyncBackend>,
+    pub rc_bisync: bool,
+    pub issues: Vec<String>,
+}
+
+impl RcloneVersion {
+    #[must_use]
+    pub const fn new(major: u32, minor: u32, patch: u32) -> Self {
+        Self {
+            major,
+            minor,
+            patch,
+        }
+    }
+
+    /// Parse the first line of `rclone version`, such as `rclone v1.68.1`.
+    #[must_use]
+    pub fn parse(line: &str) -> Option<Self> {
+        let version = line.trim().strip_prefix("rclone")?.trim_start();
+        let version = version.strip_prefix('v').unwrap_or(version);
+        let release = version.split(['-', '+', ' ']).next()?;
+
+        let mut parts = release.split('.');
+        let major = parts.next()?.parse().ok()?;
+        let minor = parts.next()?.parse().ok()?;
+        let patch = match parts.next() {
+            Some(patch) => patch.parse().ok()?,
+            None => 0,
+        };
+        if parts.next().is_some() {
+            return None;
+        }
+        Some(Self::new(major, minor, patch))
+    }
+}
+
+impl std::fmt::Display for RcloneVersion {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}.{}.{}", self.major, self.minor, self.patch)
+    }
+}
+
+impl RcloneCapabilities {
+    #[must_use]
+    pub fn for_version(version: RcloneVersion, version_line: &str) -> Self {
+        let json_log = version >= MIN_JSON_LOG_VERSION;
+        let bisync = json_log && version >= MIN_BISYNC_VERSION;
+        let rc_bisync = bisync && version >= MIN_RC_BISYNC_VERSION;
+
+        let mut issues = Vec::new();
+        let mut sync_modes = Vec::new();
+        let mut backends = Vec::new();
+        if json_log {
+            sync_modes.extend([SyncMode::Push, SyncMode::Pull]);
+            backends.extend([SyncBackend::Process, SyncBackend::RemoteControl]);
+        } else {
+            issues.push(format!(
+                "rclone {version} is too old, {MIN_JSON_LOG_VERSION} or newer is required"
+            ));
+        }
+        if bisync {
+            sync_modes.push(SyncMode::Bisync);
+        } else if json_log {
+            issues.push(format!(
+                "Bisync requires rclone {MIN_BISYNC_VERSION} or newer"
+            ));
+        }
+        if bisync && !rc_bisync {
+            issues.push(format!(
+                "Bisync with the remote-control backend requires rclone {MIN_RC_BISYNC_VERSION} or newer"
+            ));
+        }
+
+        Self {
+            version,
+            version_line: version_line.trim().to_string(),
+            managed: false,
+            supported: json_log,
+            json_log,
+            bisync,
+            sync_modes,
+            backends,
+            rc_bisync,
+            issues,
+        }
+    }
+}
+
+/// Build an rclone command with the credentials it needs supplied through
+/// the environment: the config encryption password and the secret options
+/// of every remote configured by the app, as `RCLONE_CONFIG_<REMOTE>_<OPTION>`.
+/// Secrets never appear in argv or in rclone's config file. The managed
+/// rclone binary is used when one is installed.
+///
+/// # Errors
+/// Returns an error if the command cannot be built or a secret cannot be read.
+pub fn rclone_command() -> Result<SafeCommand, String> {
+    let mut cmd = SafeCommand::new(&rclone_program())
+        .and_then(|c| c.env("RCLONE_ASK_PASSWORD", "false"))
+        .map_err(|e| format!("Failed to build rclone command: {e}"))?;
+
+    if let Some(password) = secrets::get_secret(CONFIG_PASS_KEY)? {
+        cmd = cmd
+            .env("RCLONE_CONFIG_PASS", &password)
+            .map_err(|e| format!("Failed to build rclone command: {e}"))?;
+    }
+
+    for (remote, options) in load_secret_index() {
+        for option in options {
+            let Some(value) = secrets::get_secret(&secret_key(&remote, &option))? else {
+                continue;
+            };
+            cmd = cmd
+                .env(&remote_env_var(&remote, &option), &value)
+                .map_err(|e| format!("Failed to build rclone command: {e}"))?;
+        }
+    }
+
+    Ok(cmd)
+}
+
+/// Keep secret options of a remote in the secret store instead of the
+/// rclone config. `values` are stored exactly as rclone expects them, so
+/// password-type options must already be obscured.
+///
+/// # Errors
+/// Returns an error if a secret or the index cannot be saved.
+pub fn store_remote_secrets(remote: &str, values: &[(&str, &str)]) -> Result<(), String> {
+    let mut index = load_secret_index();
+    let options = index.entry(remote.to_string()).or_default();
+
+    for (option, value) in values {
+        secrets::set_secret(&secret_key(remote, option), value)?;
+        options.insert((*option).to_string());
+    }
+    rc::invalidate_daemon();
+
+    storage::save_json(REMOTE_SECRETS_FILE, &index)
+}
+
+/// Remove every stored secret of a remote.
+///
+/// # Errors
+/// Returns an error if a secret or the index cannot be removed.
+pub fn forget_remote_secrets(remote: &str) -> Result<(), String> {
+    let mut index = load_secret_index();
+    let Some(options) = index.remove(remote) else {
+        return Ok(());
+    };
+
+    for option in options {
+        secrets::delete_secret(&secret_key(remote, &option))?;
+    }
+    rc::invalidate_daemon();
+
+    storage::save_json(REMOTE_SECRETS_FILE, &index)
+}
+
+/// Move the stored secrets of a remote to a new name.
+///
+/// # Errors
+/// Returns an error if a secret cannot be read, written or removed.
+pub fn rename_remote_secrets(old: &str, new: &str) -> Result<(), String> {
+    let mut values = Vec::new();
+    for option in stored_secret_options(old) {
+        if let Some(value) = secrets::get_secret(&secret_key(old, &option))? {
+            values.push((option, value));
+        }
+    }
+
+    let values: Vec<(&str, &str)> = values
+        .iter()
+        .map(|(k, v)| (k.as_str(), v.as_str()))
+        .collect();
+    store_remote_secrets(new, &values)?;
+    forget_remote_secrets(old)
+}
+
+/// Names of the options of a remote that are held in the secret store.
+#[must_use]
+pub fn stored_secret_options(remote: &str) -> Vec<String> {
+    load_secret_index()
+        .remove(remote)
+        .map(|options| options.into_iter().collect())
+        .unwrap_or_default()
+}
+
+/// Remote names double as environment variable names, so only ASCII
+/// letters, digits, `_` and `-` are accepted.
+///
+/// # Errors
+/// Returns an error describing why the name is rejected.
+pub fn validate_remote_name(name: &str) -> Result<(), String> {
+    if name.is_empty() || name.len() > 64 {
+        return Err("Remote name must be 1 to 64 characters".to_string());
+    }
+    if !name
+        .chars()
+        .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-')
+    {
+        return Err(format!(
+            "Invalid remote name {name}: use letters, digits, '_' or '-'"
+        ));
+    }
+    if name.starts_with('-') {
+        return Err(format!("Invalid remote name {name}: cannot start with '-'"));
+    }
+    Ok(())
+}
+
+/// Report whether the rclone config is encrypted with a password held by
+/// the app.
+///
+/// # Errors
+/// Returns an error if the secret store cannot be queried.
+#[tauri::command]
+pub fn get_config_encryption() -> Result<bool, String> {
+    Ok(secrets::get_secret(CONFIG_PASS_KEY)?.is_some())
+}
+
+/// Encrypt or decrypt the rclone config. The password is generated by the
+/// app and only ever kept in the secret store.
+///
+/// # Errors
+/// Returns an error if rclone rejects the change or the password cannot be
+/// stored.
+#[tauri::command]
+pub fn set_config_encryption(enabled: bool) -> Result<(), String> {
+    let current = secrets::get_secret(CONFIG_PASS_KEY)?;
+
+    match (enabled, current) {
+        (true, Some(_)) | (false, None) => Ok(()),
+        (true, None) => enable_config_encryption(),
+        (false, Some(_)) => {
+            let output = rclone_command()?
+                .arg("config")
+                .and_then(|c| c.arg("encryption"))
+                .and_then(|c| c.arg("remove"))
+                .map_err(|e| format!("Failed to build rclone command: {e}"))?
+                .output()
+                .map_err(|e| format!("Failed to run rclone: {e}"))?;
+
+            if !output.status.success() {
+                let stderr = String::from_utf8_lossy(&output.stderr);
+                return Err(format!(
+                    "rclone config encryption failed: {}",
+                    stderr.trim()
+                ));
+            }
+            rc::invalidate_daemon();
+            secrets::delete_secret(CONFIG_PASS_KEY)
+        }
+    }
+}
+
+fn enable_config_encryption() -> Result<(), String> {
+    let password = format!(
+        "{}{}",
+        uuid::Uuid::new_v4().simple(),
+        uuid::Uuid::new_v4().simple()
+    );
+
+    // Built before the password is stored, so rclone does not try it on
+    // the still unencrypted config.
+    let cmd = rclone_command()?;
+
+    // Store the password first: losing it after rclone has encrypted the
+    // config would make every remote unreadable.
+    secrets::set_secret(CONFIG_PASS_KEY, &password)?;
+
+    // rclone asks for the new password and its confirmation.
+    let result = cmd
+        .arg("config")
+        .and_then(|c| c.arg("encryption"))
+        .and_then(|c| c.arg("set"))
+        .map_err(|e| format!("Failed to build rclone command: {e}"))
+        .and_then(|c| output_with_input(c, &format!("{password}\n{password}\n")))
+        .and_then(|output| {
+            if output.status.success() {
+                Ok(())
+            } else {
+                let stderr = String::from_utf8_lossy(&output.stderr);
+                Err(format!(
+                    "rclone config encryption failed: {}",
+                    stderr.trim()
+                ))
+            }
+        });
+
+    if result.is_err() {
+        let _ = secrets::delete_secret(CONFIG_PASS_KEY);
+    } else {
+        rc::invalidate_daemon();
+    }
+    result
+}
+
+/// Run a command with `input` written to its stdin, capturing its output.
+/// Used to hand secrets to rclone without putting them in argv.
+///
+/// # Errors
+/// Returns an error if the process cannot be started, times out or cannot
+/// be waited for.
+#[track_caller]
+pub fn output_with_input(cmd: SafeCommand, input: &str) -> Result<Output, String> {
+    cmd.input(input.as_bytes())
+        .output()
+        .map_err(|e| format!("Failed to run rclone: {e}"))
+}
+
+/// Obscure a password the way rclone stores `pass`-type options, reading
+/// it from stdin so it never shows up in the process list.
+///
+/// # Errors
+/// Returns an error if rclone cannot be run or rejects the input.
+pub fn obscure(secret: &str) -> Result<String, String> {
+    let output = rclone_command()?
+        .arg("obscure")
+        .and_then(|c| c.arg_secret(secret))
+        .map_err(|e| format!("Failed to build rclone command: {e}"))?
+        .output()
+        .map_err(|e| format!("Failed to run rclone: {e}"))?;
+
+    if !output.status.success() {
+        return Err("rclone obscure failed".to_string());
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).trim().to_string())
+}
+
+fn load_secret_index() -> HashMap<String, BTreeSet<String>> {
+    storage::load_json(REMOTE_SECRETS_FILE)
+}
+
+fn secret_key(remote: &str, option: &str) -> String {
+    format!("remote/{remote}/{option}")
+}
+
+fn remote_env_var(remote: &str, option: &str) -> String {
+    format!("RCLONE_CONFIG_{remote}_{option}")
+        .to_uppercase()
+        .replace('-', "_")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_remote_env_var() {
+        assert_eq!(
+            remote_env_var("gbdrive", "secret_access_key"),
+            "RCLONE_CONFIG_GBDRIVE_SECRET_ACCESS_KEY"
+        );
+        assert_eq!(
+            remote_env_var("my-cloud", "pass"),
+            "RCLONE_CONFIG_MY_CLOUD_PASS"
+        );
+    }
+
+    #[test]
+    fn test_parse_version() {
+        assert_eq!(
+            RcloneVersion::parse("rclone v1.68.1"),
+            Some(RcloneVersion::new(1, 68, 1))
+        );
+        assert_eq!(
+            RcloneVersion::parse("rclone v1.66.0-DEV"),
+            Some(RcloneVersion::new(1, 66, 0))
+        );
+        assert_eq!(
+            RcloneVersion::parse("rclone v1.59.0-beta.6245.4e0b8d1a2"),
+            Some(RcloneVersion::new(1, 59, 0))
+        );
+        assert_eq!(
+            RcloneVersion::parse("rclone 1.53"),
+            Some(RcloneVersion::new(1, 53, 0))
+        );
+        assert_eq!(RcloneVersion::parse("restic 0.16.0"), None);
+        assert_eq!(RcloneVersion::parse("rclone vX.Y"), None);
+        assert!(RcloneVersion::new(1, 58, 0) < RcloneVersion::new(1, 100, 0));
+    }
+
+    #[test]
+    fn test_capabilities_by_version() {
+        let old = RcloneCapabilities::for_version(RcloneVersion::new(1, 53, 3), "rclone v1.53.3");
+        assert!(!old.supported);
+        assert!(old.sync_modes.is_empty());
+
+        let no_bisync =
+            RcloneCapabilities::for_version(RcloneVersion::new(1, 57, 0), "rclone v1.57.0");
+        assert!(no_bisync.supported);
+        assert_eq!(no_bisync.sync_modes, vec![SyncMode::Push, SyncMode::Pull]);
+        assert_eq!(no_bisync.issues.len(), 1);
+
+        let rc_limited =
+            RcloneCapabilities::for_version(RcloneVersion::new(1, 62, 2), "rclone v1.62.2");
+        assert!(rc_limited.bisync && !rc_limited.rc_bisync);
+
+        let current =
+            RcloneCapabilities::for_version(RcloneVersion::new(1, 68, 1), "rclone v1.68.1");
+        assert!(current.rc_bisync);
+        assert!(current.issues.is_empty());
+    }
+
+    #[test]
+    fn test_validate_remote_name() {
+        assert!(validate_remote_name("gbdrive").is_ok());
+        assert!(validate_remote_name("office_nas-2").is_ok());
+        assert!(validate_remote_name("").is_err());
+        assert!(validate_remote_name("-config").is_err());
+        assert!(validate_remote_name("my drive").is_err());
+        assert!(validate_remote_name("drive:").is_err());
+    }
+}
@@ -0,0 +1,881 @@
+use super::drive::{sort_file_items, FileItem};
+use super::filters::normalize_folder;
+use super::rclone::{
+    forget_remote_secrets, obscure, rclone_command, rename_remote_secrets, store_remote_secrets,
+    stored_secret_options, validate_remote_name,
+};
+use super::safe_command::SafeCommand;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::path::Path;
+use std::time::{Duration, Instant};
+
+type ConfigDump = BTreeMap<String, BTreeMap<String, String>>;
+
+/// Output limits for commands whose output is parsed as a whole: a folder
+/// listing runs to a few hundred bytes per entry.
+const LSJSON_MAX_OUTPUT: usize = 256 * 1024 * 1024;
+const CONFIG_DUMP_MAX_OUTPUT: usize = 16 * 1024 * 1024;
+
+/// Listing a large folder or totting up usage can take minutes on slow
+/// backends, well past the default timeout.
+const LSJSON_TIMEOUT: Duration = Duration::from_secs(15 * 60);
+const ABOUT_TIMEOUT: Duration = Duration::from_secs(5 * 60);
+
+/// Options that carry credentials. Anything matching these is kept out of
+/// responses to the webview.
+const SECRET_OPTIONS: [&str; 6] = [
+    "access_key_id",
+    "key_pem",
+    "sas_url",
+    "sse_customer_key_base64",
+    "service_account_credentials",
+    "client_credentials",
+];
+
+/// Secret options rclone rewrites itself, such as refreshed OAuth tokens.
+/// They stay in the rclone config instead of moving to the secret store.
+const CONFIG_SECRETS: [&str; 1] = ["token"];
+
+/// Backend settings for an rclone remote. The bucket or share to sync is not
+/// part of the remote: it goes in `SyncConfig::remote_path`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum RemoteConfig {
+    /// The botserver drive, or any other `MinIO` endpoint.
+    Minio {
+        endpoint: String,
+        access_key_id: String,
+        secret_access_key: String,
+    },
+    /// Amazon S3, or another provider reached through a custom endpoint.
+    S3 {
+        region: String,
+        access_key_id: String,
+        secret_access_key: String,
+        endpoint: Option<String>,
+    },
+    Webdav {
+        url: String,
+        vendor: WebdavVendor,
+        user: String,
+        password: String,
+    },
+    Sftp {
+        host: String,
+        port: Option<u16>,
+        user: String,
+        password: Option<String>,
+        key_file: Option<String>,
+    },
+    Smb {
+        host: String,
+        user: String,
+        password: Option<String>,
+        domain: Option<String>,
+    },
+    /// A local folder or mounted share, exposed as an rclone alias.
+    Local { path: String },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum WebdavVendor {
+    Nextcloud,
+    Owncloud,
+    Sharepoint,
+    Other,
+}
+
+/// A secret option of a remote. Password options have to be obscured
+/// before rclone accepts them, access keys are used as they are.
+struct SecretOption<'a> {
+    name: &'static str,
+    value: &'a str,
+    obscured: bool,
+}
+
+impl RemoteConfig {
+    fn rclone_type(&self) -> &'static str {
+        match self {
+            Self::Minio { .. } | Self::S3 { .. } => "s3",
+            Self::Webdav { .. } => "webdav",
+            Self::Sftp { .. } => "sftp",
+            Self::Smb { .. } => "smb",
+            Self::Local { .. } => "alias",
+        }
+    }
+
+    fn options(&self) -> Vec<(&'static str, String)> {
+        let mut options = Vec::new();
+        match self {
+            Self::Minio { endpoint, .. } => {
+                options.push(("provider", "Minio".to_string()));
+                options.push(("endpoint", endpoint.clone()));
+                options.push(("acl", "private".to_string()));
+            }
+            Self::S3 {
+                region, endpoint, ..
+            } => {
+                options.push(("provider", "AWS".to_string()));
+                options.push(("region", region.clone()));
+                if let Some(endpoint) = endpoint {
+                    options.push(("endpoint", endpoint.clone()));
+                }
+                options.push(("acl", "private".to_string()));
+            }
+            Self::Webdav {
+                url, vendor, user, ..
+            } => {
+                let vendor = match vendor {
+                    WebdavVendor::Nextcloud => "nextcloud",
+                    WebdavVendor::Owncloud => "owncloud",
+                    WebdavVendor::Sharepoint => "sharepoint",
+                    WebdavVendor::Other => "other",
+                };
+                options.push(("url", url.clone()));
+                options.push(("vendor", vendor.to_string()));
+                options.push(("user", user.clone()));
+            }
+            Self::Sftp {
+                host,
+                port,
+                user,
+                key_file,
+                ..
+            } => {
+                options.push(("host", host.clone()));
+                options.push(("user", user.clone()));
+                if let Some(port) = port {
+                    options.push(("port", port.to_string()));
+                }
+                if let Some(key_file) = key_file {
+                    options.push(("key_file", key_file.clone()));
+                }
+            }
+            Self::Smb {
+                host, user, domain, ..
+            } => {
+                options.push(("host", host.clone()));
+                options.push(("user", user.clone()));
+                if let Some(domain) = domain {
+                    options.push(("domain", domain.clone()));
+                }
+            }
+            Self::Local { path } => options.push(("remote", path.clone())),
+        }
+        options
+    }
+
+    fn secrets(&self) -> Vec<SecretOption<'_>> {
+        let key = |name, value| SecretOption {
+            name,
+            value,
+            obscured: false,
+        };
+        let pass = |value| SecretOption {
+            name: "pass",
+            value,
+            obscured: true,
+        };
+
+        match self {
+            Self::Minio {
+                access_key_id,
+                secret_access_key,
+                ..
+            }
+            | Self::S3 {
+                access_key_id,
+                secret_access_key,
+                ..
+            } => vec![
+                key("access_key_id", access_key_id),
+                key("secret_access_key", secret_access_key),
+            ],
+            Self::Webdav { password, .. } => vec![pass(password)],
+            Self::Sftp { password, .. } | Self::Smb { password, .. } => {
+                password.as_deref().map(pass).into_iter().collect()
+            }
+            Self::Local { .. } => Vec::new(),
+        }
+    }
+
+    /// Check the fields before anything is written to the rclone config.
+    ///
+    /// # Errors
+    /// Returns a message naming the first invalid field.
+    pub fn validate(&self) -> Result<(), String> {
+        match self {
+            Self::Minio {
+                endpoint,
+                access_key_id,
+                secret_access_key,
+            } => {
+                validate_url("endpoint", endpoint)?;
+                require("access_key_id", access_key_id)?;
+                require("secret_access_key", secret_access_key)
+            }
+            Self::S3 {
+                region,
+                access_key_id,
+                secret_access_key,
+                endpoint,
+            } => {
+                require("region", region)?;
+                if let Some(endpoint) = endpoint {
+                    validate_url("endpoint", endpoint)?;
+                }
+                require("access_key_id", access_key_id)?;
+                require("secret_access_key", secret_access_key)
+            }
+            Self::Webdav {
+                url,
+                user,
+                password,
+                ..
+            } => {
+                validate_url("url", url)?;
+                require("user", user)?;
+                require("password", password)
+            }
+            Self::Sftp {
+                host,
+                port,
+                user,
+                password,
+                key_file,
+            } => {
+                validate_host(host)?;
+                require("user", user)?;
+                if *port == Some(0) {
+                    return Err("port must be between 1 and 65535".to_string());
+                }
+                match (password, key_file) {
+                    (None, None) => Err("SFTP needs a password or a key file".to_string()),
+                    (_, Some(key_file)) if !Path::new(key_file).is_file() => {
+                        Err(format!("key_file {key_file} does not exist"))
+                    }
+                    _ => Ok(()),
+                }
+            }
+            Self::Smb { host, user, .. } => {
+                validate_host(host)?;
+                require("user", user)
+            }
+            Self::Local { path } => {
+                let path = Path::new(path);
+                if !path.is_absolute() || !path.is_dir() {
+                    return Err("path must be an existing absolute directory".to_string());
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct RemoteUsage {
+    pub total: Option<u64>,
+    pub used: Option<u64>,
+    pub free: Option<u64>,
+    pub trashed: Option<u64>,
+    pub other: Option<u64>,
+    pub objects: Option<u64>,
+}
+
+/// A remote as reported to the UI. `secrets` lists the secret options that
+/// are set, without their values.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RemoteInfo {
+    pub name: String,
+    pub remote_type: String,
+    pub options: BTreeMap<String, String>,
+    pub secrets: Vec<String>,
+}
+
+/// One entry of `rclone lsjson` output.
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct RemoteEntry {
+    name: String,
+    size: i64,
+    is_dir: bool,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RemoteTestResult {
+    pub remote: String,
+    pub ok: bool,
+    pub directories: Vec<String>,
+    pub usage: Option<RemoteUsage>,
+    pub error: Option<String>,
+    pub elapsed_ms: u64,
+}
+
+/// Create or replace an rclone remote. Secret options are kept in the
+/// secret store and handed to rclone through the environment.
+///
+/// # Errors
+/// Returns an error if the name or fields are invalid, or rclone rejects the
+/// configuration.
+#[tauri::command]
+pub fn configure_remote(remote_name: &str, config: RemoteConfig) -> Result<(), String> {
+    validate_remote_name(remote_name)?;
+    config.validate()?;
+
+    let cmd = rclone_command()?
+        .arg("config")
+        .and_then(|c| c.arg("create"))
+        .and_then(|c| c.arg(remote_name))
+        .and_then(|c| c.arg(config.rclone_type()))
+        .map_err(|e| format!("Failed to build rclone command: {e}"))?;
+
+    apply_remote_config(cmd, remote_name, &config)
+}
+
+/// Describe a remote, with every credential redacted.
+///
+/// # Errors
+/// Returns an error if the remote does not exist or rclone cannot be run.
+#[tauri::command]
+pub fn get_remote(remote_name: &str) -> Result<RemoteInfo, String> {
+    let mut dump = config_dump()?;
+    let options = dump
+        .remove(remote_name)
+        .ok_or_else(|| format!("Remote {remote_name} does not exist"))?;
+    Ok(redact_remote(
+        remote_name,
+        options,
+        stored_secret_options(remote_name),
+    ))
+}
+
+/// Change the settings of an existing remote. Options not set by `config`
+/// keep their current values; stored secrets are replaced.
+///
+/// # Errors
+/// Returns an error if the remote does not exist, the backend type differs,
+/// or rclone rejects the update.
+#[tauri::command]
+pub fn update_remote(remote_name: &str, config: RemoteConfig) -> Result<(), String> {
+    validate_remote_name(remote_name)?;
+    config.validate()?;
+
+    let current = get_remote(remote_name)?;
+    if current.remote_type != config.rclone_type() {
+        return Err(format!(
+            "Remote {remote_name} is of type {}, delete and recreate it to change its type",
+            current.remote_type
+        ));
+    }
+
+    let cmd = rclone_command()?
+        .arg("config")
+        .and_then(|c| c.arg("update"))
+        .and_then(|c| c.arg(remote_name))
+        .map_err(|e| format!("Failed to build rclone command: {e}"))?;
+
+    apply_remote_config(cmd, remote_name, &config)
+}
+
+/// Remove a remote and its stored secrets.
+///
+/// # Errors
+/// Returns an error if rclone cannot delete the remote.
+#[tauri::command]
+pub fn delete_remote(remote_name: &str) -> Result<(), String> {
+    validate_remote_name(remote_name)?;
+
+    let output = rclone_command()?
+        .arg("config")
+        .and_then(|c| c.arg("delete"))
+        .and_then(|c| c.arg(remote_name))
+        .map_err(|e| format!("Failed to build rclone command: {e}"))?
+        .output()
+        .map_err(|e| format!("Failed to run rclone: {e}"))?;
+
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        return Err(format!("rclone config delete failed: {}", stderr.trim()));
+    }
+
+    forget_remote_secrets(remote_name)
+}
+
+/// Rename a remote. Sync profiles that use the old name need updating, and
+/// their next bisync run will resync.
+///
+/// # Errors
+/// Returns an error if either name is invalid, the new name is taken, or
+/// rclone cannot create or delete the remotes.
+#[tauri::command]
+pub fn rename_remote(remote_name: &str, new_name: &str) -> Result<(), String> {
+    validate_remote_name(remote_name)?;
+    validate_remote_name(new_name)?;
+
+    let mut dump = config_dump()?;
+    if dump.contains_key(new_name) {
+        return Err(format!("Remote {new_name} already exists"));
+    }
+    let mut options = dump
+        .remove(remote_name)
+        .ok_or_else(|| format!("Remote {remote_name} does not exist"))?;
+    let remote_type = options
+        .remove("type")
+        .ok_or_else(|| format!("Remote {remote_name} has no type"))?;
+
+    // Secrets written to the config by other tools move to the secret store,
+    // so they never have to pass through argv.
+    let (secrets, options): (Vec<_>, Vec<_>) = options
+        .into_iter()
+        .partition(|(option, _)| is_secret_option(option));
+    let (config_secrets, secrets): (Vec<_>, Vec<_>) = secrets
+        .into_iter()
+        .partition(|(option, _)| CONFIG_SECRETS.contains(&option.as_str()));
+
+    let mut cmd = rclone_command()?
+        .arg("config")
+        .and_then(|c| c.arg("create"))
+        .and_then(|c| c.arg(new_name))
+        .and_then(|c| c.arg(&remote_type))
+        .map_err(|e| format!("Failed to build rclone command: {e}"))?;
+    for (option, value) in &options {
+        cmd = cmd
+            .arg(option)
+            .and_then(|c| c.arg(value))
+            .map_err(|e| format!("Invalid value for {option}: {e}"))?;
+    }
+    for (option, value) in &config_secrets {
+        cmd = cmd
+            .arg(option)
+            .and_then(|c| c.arg_secret(value))
+            .map_err(|e| format!("Invalid value for {option}: {e}"))?;
+    }
+    run_config_command(cmd.arg("--non-interactive"))?;
+
+    rename_remote_secrets(remote_name, new_name)?;
+    let secrets: Vec<(&str, &str)> = secrets
+        .iter()
+        .map(|(k, v)| (k.as_str(), v.as_str()))
+        .collect();
+    store_remote_secrets(new_name, &secrets)?;
+
+    delete_remote(remote_name)
+}
+
+/// Check that a remote is reachable by listing its top-level directories,
+/// and report its usage when the backend supports it.
+///
+/// # Errors
+/// Returns an error if rclone cannot be run at all; connection failures are
+/// reported in the result.
+#[tauri::command]
+pub fn test_remote(remote_name: &str) -> Result<RemoteTestResult, String> {
+    validate_remote_name(remote_name)?;
+    let started = Instant::now();
+    let root = format!("{remote_name}:");
+
+    let output = rclone_command()?
+        .arg("lsd")
+        .and_then(|c| c.arg(&root))
+        .map_err(|e| format!("Failed to build rclone command: {e}"))?
+        .output()
+        .map_err(|e| format!("Failed to run rclone: {e}"))?;
+
+    let ok = output.status.success();
+    let (directories, error) = if ok {
+        let stdout = String::from_utf8_lossy(&output.stdout);
+        (stdout.lines().filter_map(parse_lsd_line).collect(), None)
+    } else {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        (Vec::new(), Some(stderr.trim().to_string()))
+    };
+
+    let usage = if ok { remote_usage(&root).ok() } else { None };
+
+    Ok(RemoteTestResult {
+        remote: remote_name.to_string(),
+        ok,
+        directories,
+        usage,
+        error,
+        elapsed_ms: u64::try_from(started.elapsed().as_millis()).unwrap_or(u64::MAX),
+    })
+}
+
+/// List a folder on a remote without syncing it, in the same shape as
+/// `drive::list_files` so both can be shown side by side.
+///
+/// # Errors
+/// Returns an error if the remote name is invalid or rclone cannot list the
+/// folder.
+#[tauri::command]
+pub fn list_remote_files(remote_name: &str, path: &str) -> Result<Vec<FileItem>, String> {
+    validate_remote_name(remote_name)?;
+    let folder = remote_folder(path)?;
+    let spec = format!("{remote_name}:{folder}");
+
+    let output = rclone_command()?
+        .arg("lsjson")
+        .and_then(|c| c.arg(&spec))
+        .and_then(|c| c.arg("--no-mimetype"))
+        .map_err(|e| format!("Failed to build rclone command: {e}"))?
+        .timeout(LSJSON_TIMEOUT)
+        .max_output(LSJSON_MAX_OUTPUT)
+        .output()
+        .map_err(|e| format!("Failed to run rclone: {e}"))?;
+
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        return Err(format!("rclone lsjson failed: {}", stderr.trim()));
+    }
+
+    let entries: Vec<RemoteEntry> = serde_json::from_slice(&output.stdout)
+        .map_err(|e| format!("Invalid rclone lsjson output: {e}"))?;
+
+    let mut files: Vec<FileItem> = entries
+        .into_iter()
+        .map(|entry| to_file_item(remote_name, &folder, entry))
+        .collect();
+    sort_file_items(&mut files);
+
+    Ok(files)
+}
+
+/// A folder to list on a remote, where an empty path is the remote root.
+fn remote_folder(path: &str) -> Result<String, String> {
+    if path.trim().trim_matches('/').is_empty() {
+        Ok(String::new())
+    } else {
+        normalize_folder(path)
+    }
+}
+
+/// Report quota and usage of a remote.
+///
+/// # Errors
+/// Returns an error if the backend does not support `rclone about` or the
+/// remote cannot be reached.
+#[tauri::command]
+pub fn remote_about(remote_name: &str) -> Result<RemoteUsage, String> {
+    validate_remote_name(remote_name)?;
+    remote_usage(&format!("{remote_name}:"))
+}
+
+#[tauri::command]
+pub fn list_remotes() -> Result<Vec<String>, String> {
+    let output = rclone_command()?
+        .arg("listremotes")
+        .map_err(|e| format!("Failed to build rclone command: {e}"))?
+        .output()
+        .map_err(|e| format!("Failed to list remotes: {e}"))?;
+
+    if output.status.success() {
+        let remotes = String::from_utf8_lossy(&output.stdout);
+        Ok(remotes
+            .lines()
+            .map(|s| s.trim_end_matches(':').to_string())
+            .filter(|s| !s.is_empty())
+            .collect())
+    } else {
+        Err("Failed to list rclone remotes".to_string())
+    }
+}
+
+fn apply_remote_config(
+    mut cmd: SafeCommand,
+    remote_name: &str,
+    config: &RemoteConfig,
+) -> Result<(), String> {
+    for (option, value) in config.options() {
+        cmd = cmd
+            .arg(option)
+            .and_then(|c| c.arg(&value))
+            .map_err(|e| format!("Invalid value for {option}: {e}"))?;
+    }
+    run_config_command(Ok(cmd))?;
+
+    let mut secrets = Vec::new();
+    for secret in config.secrets() {
+        let value = if secret.obscured {
+            obscure(secret.value)?
+        } else {
+            secret.value.to_string()
+        };
+        secrets.push((secret.name, value));
+    }
+
+    forget_remote_secrets(remote_name)?;
+    let secrets: Vec<(&str, &str)> = secrets.iter().map(|(k, v)| (*k, v.as_str())).collect();
+    store_remote_secrets(remote_name, &secrets)
+}
+
+fn run_config_command(
+    cmd: Result<SafeCommand, super::safe_command::SafeCommandError>,
+) -> Result<(), String> {
+    let output = cmd
+        .map_err(|e| format!("Failed to build rclone command: {e}"))?
+        .output()
+        .map_err(|e| format!("Failed to configure rclone: {e}"))?;
+
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        return Err(format!("rclone config failed: {stderr}"));
+    }
+    Ok(())
+}
+
+fn config_dump() -> Result<ConfigDump, String> {
+    let output = rclone_command()?
+        .arg("config")
+        .and_then(|c| c.arg("dump"))
+        .map_err(|e| format!("Failed to build rclone command: {e}"))?
+        .max_output(CONFIG_DUMP_MAX_OUTPUT)
+        .output()
+        .map_err(|e| format!("Failed to run rclone: {e}"))?;
+
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        return Err(format!("rclone config dump failed: {}", stderr.trim()));
+    }
+
+    serde_json::from_slice(&output.stdout).map_err(|e| format!("Invalid rclone config dump: {e}"))
+}
+
+fn is_secret_option(option: &str) -> bool {
+    SECRET_OPTIONS.contains(&option)
+        || option == "key"
+        || option.ends_with("_key")
+        || ["pass", "secret", "token"]
+            .iter()
+            .any(|marker| option.contains(marker))
+}
+
+fn redact_remote(
+    name: &str,
+    mut options: BTreeMap<String, String>,
+    mut secrets: Vec<String>,
+) -> RemoteInfo {
+    let remote_type = options.remove("type").unwrap_or_default();
+
+    options.retain(|option, _| {
+        let secret = is_secret_option(option);
+        if secret && !secrets.contains(option) {
+            secrets.push(option.clone());
+        }
+        !secret
+    });
+    secrets.sort();
+
+    RemoteInfo {
+        name: name.to_string(),
+        remote_type,
+        options,
+        secrets,
+    }
+}
+
+fn remote_usage(root: &str) -> Result<RemoteUsage, String> {
+    let output = rclone_command()?
+        .arg("about")
+        .and_then(|c| c.arg(root))
+        .and_then(|c| c.arg("--json"))
+        .map_err(|e| format!("Failed to build rclone command: {e}"))?
+        .timeout(ABOUT_TIMEOUT)
+        .output()
+        .map_err(|e| format!("Failed to run rclone: {e}"))?;
+
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        return Err(format!("rclone about failed: {}", stderr.trim()));
+    }
+
+    serde_json::from_slice(&output.stdout).map_err(|e| format!("Invalid rclone about output: {e}"))
+}
+
+fn to_file_item(remote_name: &str, folder: &str, entry: RemoteEntry) -> FileItem {
+    let path = if folder.is_empty() {
+        format!("{remote_name}:{}", entry.name)
+    } else {
+        format!("{remote_name}:{folder}/{}", entry.name)
+    };
+
+    FileItem {
+        path,
+        size: if entry.is_dir {
+            None
+        } else {
+            u64::try_from(entry.size).ok()
+        },
+        is_dir: entry.is_dir,
+        name: entry.name,
+    }
+}
+
+/// Extract the directory name from a line of `rclone lsd` output, which is
+/// size, date, time and count followed by the name.
+fn parse_lsd_line(line: &str) -> Option<String> {
+    let mut rest = line.trim_start();
+    for _ in 0..4 {
+        let end = rest.find(char::is_whitespace)?;
+        rest = rest[end..].trim_start();
+    }
+    (!rest.is_empty()).then(|| rest.to_string())
+}
+
+fn require(field: &str, value: &str) -> Result<(), String> {
+    if value.trim().is_empty() {
+        return Err(format!("{field} is required"));
+    }
+    Ok(())
+}
+
+fn validate_url(field: &str, value: &str) -> Result<(), String> {
+    require(field, value)?;
+    if !value.starts_with("https://") && !value.starts_with("http://") {
+        return Err(format!("{field} must be an http:// or https:// URL"));
+    }
+    Ok(())
+}
+
+fn validate_host(host: &str) -> Result<(), String> {
+    require("host", host)?;
+    if host.starts_with('-') || host.contains(|c: char| c.is_whitespace() || c == '/') {
+        return Err(format!("Invalid host: {host}"));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_lsd_line() {
+        assert_eq!(
+            parse_lsd_line("          -1 2024-03-01 10:00:00        -1 bots"),
+            Some("bots".to_string())
+        );
+        assert_eq!(
+            parse_lsd_line("           0 2024-03-01 10:00:00         3 My Documents"),
+            Some("My Documents".to_string())
+        );
+        assert_eq!(parse_lsd_line(""), None);
+    }
+
+    #[test]
+    fn test_lsjson_to_file_items() {
+        let entries: Vec<RemoteEntry> = serde_json::from_str(
+            r#"[{"Path":"bots/a.txt","Name":"a.txt","Size":12,"IsDir":false},
+                {"Path":"bots/sub","Name":"sub","Size":-1,"IsDir":true}]"#,
+        )
+        .unwrap();
+        let items: Vec<FileItem> = entries
+            .into_iter()
+            .map(|e| to_file_item("gbdrive", "bots", e))
+            .collect();
+
+        assert_eq!(items[0].path, "gbdrive:bots/a.txt");
+        assert_eq!(items[0].size, Some(12));
+        assert_eq!(items[1].path, "gbdrive:bots/sub");
+        assert_eq!(items[1].size, None);
+        assert!(items[1].is_dir);
+    }
+
+    #[test]
+    fn test_validate_remote_config() {
+        let minio = RemoteConfig::Minio {
+            endpoint: "https://drive.example.com".to_string(),
+            access_key_id: "key".to_string(),
+            secret_access_key: "secret".to_string(),
+        };
+        assert!(minio.validate().is_ok());
+
+        let webdav = RemoteConfig::Webdav {
+            url: "ftp://cloud.example.com".to_string(),
+            vendor: WebdavVendor::Nextcloud,
+            user: "ana".to_string(),
+            password: "pw".to_string(),
+        };
+        assert!(webdav.validate().is_err());
+
+        let sftp = RemoteConfig::Sftp {
+            host: "files.example.com".to_string(),
+            port: Some(22),
+            user: "ana".to_string(),
+            password: None,
+            key_file: None,
+        };
+        assert!(sftp.validate().is_err());
+    }
+
+    #[test]
+    fn test_secrets_stay_out_of_options() {
+        let config = RemoteConfig::S3 {
+            region: "us-east-1".to_string(),
+            access_key_id: "AKIA".to_string(),
+            secret_access_key: "shh".to_string(),
+            endpoint: None,
+        };
+        assert!(config
+            .options()
+            .iter()
+            .all(|(_, value)| value != "AKIA" && value != "shh"));
+        assert_eq!(config.secrets().len(), 2);
+        assert_eq!(config.rclone_type(), "s3");
+    }
+
+    #[test]
+    fn test_redact_remote() {
+        let options = BTreeMap::from([
+            ("type".to_string(), "webdav".to_string()),
+            ("url".to_string(), "https://cloud.example.com".to_string()),
+            ("user".to_string(), "ana".to_string()),
+            ("pass".to_string(), "obscured".to_string()),
+            ("bearer_token".to_string(), "abc".to_string()),
+        ]);
+        let info = redact_remote("nextcloud", options, Vec::new());
+
+        assert_eq!(info.remote_type, "webdav");
+        assert_eq!(info.options.len(), 2);
+        assert!(info.options.values().all(|v| v != "obscured" && v != "abc"));
+        assert_eq!(info.secrets, vec!["bearer_token", "pass"]);
+    }
+
+    #[test]
+    fn test_remote_folder() {
+        assert_eq!(remote_folder("").unwrap(), "");
+        assert_eq!(remote_folder("/").unwrap(), "");
+        assert_eq!(remote_folder("/docs/./2024/").unwrap(), "docs/2024");
+        assert!(remote_folder("..").is_err());
+        assert!(remote_folder("docs/../../etc").is_err());
+    }
+
+    #[test]
+    fn test_is_secret_option() {
+        for option in [
+            "key",
+            "api_key",
+            "app_key",
+            "account_key",
+            "sas_url",
+            "sse_customer_key",
+            "access_key_id",
+            "client_secret",
+            "token",
+        ] {
+            assert!(is_secret_option(option), "{option}");
+        }
+        for option in ["url", "endpoint", "region", "user", "key_file", "account"] {
+            assert!(!is_secret_option(option), "{option}");
+        }
+    }
+
+    #[test]
+    fn test_remote_config_serde_tag() {
+        let config: RemoteConfig =
+            serde_json::from_str(r#"{"type":"local","path":"/srv/share"}"#).unwrap();
+        assert!(matches!(config, RemoteConfig::Local { .. }));
+    }
+}
@@ -1,21 +1,51 @@
-use std::collections::HashSet;
-use std::path::PathBuf;
-use std::process::{Child, Command, Output, Stdio};
-use std::sync::LazyLock;
-
-static ALLOWED_COMMANDS: LazyLock<HashSet<&'static str>> = LazyLock::new(|| {
-    HashSet::from([
-        "rclone",
-        "notify-send",
-        "osascript",
-    ])
-});
-
-static FORBIDDEN_SHELL_CHARS: LazyLock<HashSet<char>> = LazyLock::new(|| {
-    HashSet::from([
-        ';', '|', '&', '$', '`', '(', ')', '{', '}', '<', '>', '\n', '\r', '\0',
-    ])
-});
+use super::audit::{self, AuditRecord, REDACTED};
+use super::command_path;
+use super::command_policy::{policy_for, CommandPolicy, Validator};
+use super::sandbox::{self, Sandbox};
+use std::io::{self, Read, Write};
+use std::panic::Location;
+use std::path::{Path, PathBuf};
+use std::process::{Child, Command, ExitStatus, Output, Stdio};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+use tokio::io::{AsyncBufReadExt, BufReader, Lines};
+use tokio::process::{ChildStderr, ChildStdout};
+use zeroize::Zeroizing;
+
+/// How long [`SafeCommand::output`] waits unless told otherwise.
+pub const DEFAULT_TIMEOUT: Duration = Duration::from_secs(60);
+/// Bytes of stdout and of stderr [`SafeCommand::output`] accepts by default.
+pub const DEFAULT_MAX_OUTPUT: usize = 4 * 1024 * 1024;
+const WAIT_POLL_INTERVAL: Duration = Duration::from_millis(20);
+
+/// Variables a child inherits from the app environment, compared without
+/// case. Everything else, including `RCLONE_*` settings of the user's shell,
+/// must be passed explicitly with [`SafeCommand::env`].
+const INHERITED_ENV: &[&str] = &[
+    "PATH",
+    "HOME",
+    "USER",
+    "LOGNAME",
+    "LANG",
+    "LANGUAGE",
+    "TZ",
+    "TMPDIR",
+    "TEMP",
+    "TMP",
+    "DISPLAY",
+    "WAYLAND_DISPLAY",
+    "DBUS_SESSION_BUS_ADDRESS",
+    "SYSTEMROOT",
+    "USERPROFILE",
+    "APPDATA",
+    "LOCALAPPDATA",
+    "HTTP_PROXY",
+    "HTTPS_PROXY",
+    "NO_PROXY",
+    "SSL_CERT_FILE",
+    "SSL_CERT_DIR",
+];
+const INHERITED_ENV_PREFIXES: &[&str] = &["LC_", "XDG_"];
 
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub enum SafeCommandError {
@@ -23,6 +53,14 @@ pub enum SafeCommandError {
     InvalidArgument(String),
     ExecutionFailed(String),
     ShellInjectionAttempt(String),
+    FlagNotAllowed(String),
+    SubcommandNotAllowed(String),
+    MissingFlagValue(String),
+    UntrustedExecutable(String),
+    TimedOut(Duration),
+    OutputTooLarge(usize),
+    Io(String),
+    InvalidWorkingDir(String),
 }
 
 impl std::fmt::Display for SafeCommandError {
@@ -34,6 +72,14 @@ impl std::fmt::Display for SafeCommandError {
             Self::ShellInjectionAttempt(input) => {
                 write!(f, "Shell injection attempt detected: {input}")
             }
+            Self::FlagNotAllowed(flag) => write!(f, "Flag not allowed: {flag}"),
+            Self::SubcommandNotAllowed(sub) => write!(f, "Subcommand not allowed: {sub}"),
+            Self::MissingFlagValue(flag) => write!(f, "Missing value for flag: {flag}"),
+            Self::UntrustedExecutable(msg) => write!(f, "Untrusted executable: {msg}"),
+            Self::TimedOut(timeout) => write!(f, "Command timed out after {timeout:?}"),
+            Self::OutputTooLarge(max) => write!(f, "Command output exceeded {max} bytes"),
+            Self::Io(msg) => write!(f, "Command I/O failed: {msg}"),
+            Self::InvalidWorkingDir(msg) => write!(f, "Invalid working directory: {msg}"),
         }
     }
 }
@@ -42,38 +88,279 @@ impl std::error::Error for SafeCommandError {}
 
 pub struct SafeCommand {
     command: String,
-    args: Vec<String>,
+    policy: &'static CommandPolicy,
+    args: Vec<Arg>,
+    subcommand: Vec<String>,
+    pending_flag: Option<(&'static str, Validator)>,
+    options_ended: bool,
     working_dir: Option<PathBuf>,
+    envs: Vec<(String, Zeroizing<String>)>,
+    removed_envs: Vec<String>,
+    inherit_env: bool,
+    stdin: Option<Stdio>,
     stdout: Option<Stdio>,
     stderr: Option<Stdio>,
+    input: Option<Zeroizing<Vec<u8>>>,
+    timeout: Duration,
+    max_output: usize,
+    sandbox: Option<Sandbox>,
+}
+
+/// A command-line argument. Secret values are wiped from memory when the
+/// command is dropped and never shown in `Debug` output or the audit log.
+enum Arg {
+    Plain(String),
+    Secret(Zeroizing<String>),
+}
+
+impl Arg {
+    fn expose(&self) -> &str {
+        match self {
+            Self::Plain(value) => value,
+            Self::Secret(value) => value,
+        }
+    }
+}
+
+impl std::fmt::Debug for SafeCommand {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let envs: Vec<&str> = self.envs.iter().map(|(k, _)| k.as_str()).collect();
+        f.debug_struct("SafeCommand")
+            .field("command", &self.command)
+            .field("args", &self.redacted_args())
+            .field("working_dir", &self.working_dir)
+            .field("envs", &envs)
+            .field("timeout", &self.timeout)
+            .field("sandbox", &self.sandbox)
+            .finish_non_exhaustive()
+    }
 }
 
 impl SafeCommand {
+    /// Start a command for an allowed program, given by name or absolute
+    /// path. The executable is resolved to a trusted location when the
+    /// command runs.
     pub fn new(command: &str) -> Result<Self, SafeCommandError> {
-        let cmd_name = std::path::Path::new(command)
+        let path = Path::new(command);
+        if !path.is_absolute() && path.components().count() != 1 {
+            return Err(SafeCommandError::CommandNotAllowed(command.to_string()));
+        }
+
+        let cmd_name = path
             .file_name()
             .and_then(|n| n.to_str())
             .unwrap_or(command);
+        #[cfg(windows)]
+        let cmd_name = cmd_name.strip_suffix(".exe").unwrap_or(cmd_name);
 
-        if !ALLOWED_COMMANDS.contains(cmd_name) {
+        let Some(policy) = policy_for(cmd_name) else {
             return Err(SafeCommandError::CommandNotAllowed(command.to_string()));
-        }
+        };
 
         Ok(Self {
             command: command.to_string(),
+            policy,
             args: Vec::new(),
+            subcommand: Vec::new(),
+            pending_flag: None,
+            options_ended: false,
             working_dir: None,
+            envs: Vec::new(),
+            removed_envs: Vec::new(),
+            inherit_env: true,
+            stdin: None,
             stdout: None,
             stderr: None,
+            input: None,
+            timeout: DEFAULT_TIMEOUT,
+            max_output: DEFAULT_MAX_OUTPUT,
+            sandbox: None,
         })
     }
 
+    /// Add an argument, checked against the program's policy: a listed flag,
+    /// the value of the preceding flag, the next word of a subcommand, or a
+    /// positional argument.
     pub fn arg(mut self, arg: &str) -> Result<Self, SafeCommandError> {
-        validate_argument(arg)?;
-        self.args.push(arg.to_string());
+        validate_argument(arg, self.policy.forbidden)?;
+        self.check_policy(arg)?;
+        self.args.push(Arg::Plain(arg.to_string()));
+        Ok(self)
+    }
+
+    /// Add a secret, either as the value of the preceding flag or as a
+    /// positional argument. When the policy allows it, the secret goes
+    /// through the environment (flag values) or stdin (positional
+    /// arguments of `secret_stdin` subcommands) instead of argv. Either way
+    /// it is masked in `Debug` output and the audit log, and errors never
+    /// include it.
+    pub fn arg_secret(mut self, secret: &str) -> Result<Self, SafeCommandError> {
+        let policy = self.policy;
+        let rejected = |what: &str| {
+            SafeCommandError::InvalidArgument(format!("Secret argument rejected: {what}"))
+        };
+
+        if let Some((flag, validator)) = self.pending_flag.take() {
+            validator(secret).map_err(|_| rejected(flag))?;
+            if let Some(flag_env) = policy.flag_env {
+                // Drop the flag itself; the program reads it from the env,
+                // where NUL is the only byte that cannot be passed.
+                if secret.contains('\0') {
+                    return Err(rejected("invalid value"));
+                }
+                self.args.pop();
+                self.envs.retain(|(k, _)| *k != flag_env(flag));
+                self.envs
+                    .push((flag_env(flag), Zeroizing::new(secret.to_string())));
+            } else {
+                validate_argument(secret, policy.forbidden)
+                    .map_err(|_| rejected("invalid value"))?;
+                self.args.push(Arg::Secret(Zeroizing::new(secret.to_string())));
+            }
+            return Ok(self);
+        }
+
+        if !self.subcommand_complete() {
+            return Err(rejected("expected a flag value or positional argument"));
+        }
+
+        let via_stdin = self.input.is_none()
+            && policy
+                .secret_stdin
+                .iter()
+                .any(|sub| sub.iter().eq(self.subcommand.iter()));
+        if via_stdin {
+            // Nothing reaches argv, so any secret is fine, even one that
+            // looks like a flag.
+            self.args.push(Arg::Plain("-".to_string()));
+            self.input = Some(Zeroizing::new(secret.as_bytes().to_vec()));
+            return Ok(self);
+        }
+
+        validate_argument(secret, policy.forbidden).map_err(|_| rejected("invalid value"))?;
+        if secret.starts_with('-') && !self.options_ended {
+            return Err(rejected("expected a flag value or positional argument"));
+        }
+        (policy.positional)(secret).map_err(|_| rejected("invalid value"))?;
+        self.args.push(Arg::Secret(Zeroizing::new(secret.to_string())));
         Ok(self)
     }
 
+    fn check_policy(&mut self, arg: &str) -> Result<(), SafeCommandError> {
+        let policy = self.policy;
+
+        if let Some((flag, validator)) = self.pending_flag.take() {
+            return validator(arg)
+                .map_err(|e| SafeCommandError::InvalidArgument(format!("{flag}: {e}")));
+        }
+
+        if arg.starts_with('-') && arg != "-" && !self.options_ended {
+            let (flag, inline_value) = match arg.split_once('=') {
+                Some((flag, value)) => (flag, Some(value)),
+                None => (arg, None),
+            };
+            if let Some((name, validator)) = policy.value_flag(flag) {
+                match inline_value {
+                    Some(value) => validator(value)
+                        .map_err(|e| SafeCommandError::InvalidArgument(format!("{name}: {e}")))?,
+                    None => self.pending_flag = Some((name, validator)),
+                }
+                return Ok(());
+            }
+            if inline_value.is_none() && policy.flags.contains(&flag) {
+                self.options_ended = flag == "--";
+                return Ok(());
+            }
+            return Err(SafeCommandError::FlagNotAllowed(format!(
+                "{flag} for {}",
+                policy.name
+            )));
+        }
+
+        if !self.subcommand_complete() {
+            self.subcommand.push(arg.to_string());
+            if !policy.matches_subcommand(&self.subcommand, true) {
+                return Err(SafeCommandError::SubcommandNotAllowed(format!(
+                    "{} {}",
+                    policy.name,
+                    self.subcommand.join(" ")
+                )));
+            }
+            return Ok(());
+        }
+
+        (policy.positional)(arg).map_err(SafeCommandError::InvalidArgument)
+    }
+
+    fn subcommand_complete(&self) -> bool {
+        self.policy.subcommands.is_empty()
+            || self.policy.matches_subcommand(&self.subcommand, false)
+    }
+
+    /// Reject a command line that stops inside a subcommand or before the
+    /// value of its last flag.
+    fn check_complete(&self) -> Result<(), SafeCommandError> {
+        if let Some((flag, _)) = self.pending_flag {
+            return Err(SafeCommandError::MissingFlagValue(flag.to_string()));
+        }
+        if !self.subcommand_complete() {
+            return Err(SafeCommandError::SubcommandNotAllowed(format!(
+                "{} {}",
+                self.policy.name,
+                self.subcommand.join(" ")
+            )));
+        }
+        Ok(())
+    }
+
+    /// Set an environment variable for the child. Values are never validated
+    /// or logged, so this is the channel for credentials that must stay out
+    /// of the process argument list.
+    pub fn env(mut self, key: &str, value: &str) -> Result<Self, SafeCommandError> {
+        if key.is_empty() || key.contains(['=', '\0']) {
+            return Err(SafeCommandError::InvalidArgument(format!(
+                "Invalid environment variable name: {key}"
+            )));
+        }
+        self.envs.retain(|(k, _)| k != key);
+        self.envs
+            .push((key.to_string(), Zeroizing::new(value.to_string())));
+        Ok(self)
+    }
+
+    /// Run the command in `dir`, which must exist, lie within the app data
+    /// directory, the user's home or the temporary directory, and not be
+    /// world-writable.
+    pub fn current_dir(mut self, dir: &Path) -> Result<Self, SafeCommandError> {
+        let dir = command_path::check_working_dir(dir, &command_path::working_dir_roots())
+            .map_err(SafeCommandError::InvalidWorkingDir)?;
+        self.working_dir = Some(dir);
+        Ok(self)
+    }
+
+    /// Unset a variable, whether set with [`SafeCommand::env`] or inherited.
+    #[must_use]
+    pub fn env_remove(mut self, key: &str) -> Self {
+        self.envs.retain(|(k, _)| k != key);
+        self.removed_envs.push(key.to_string());
+        self
+    }
+
+    /// Start the child with only the variables set after this call, not
+    /// even the inherited ones.
+    #[must_use]
+    pub fn env_clear(mut self) -> Self {
+        self.envs.clear();
+        self.inherit_env = false;
+        self
+    }
+
+    #[must_use]
+    pub fn stdin(mut self, stdin: Stdio) -> Self {
+        self.stdin = Some(stdin);
+        self
+    }
+
     #[must_use]
     pub fn stdout(mut self, stdout: Stdio) -> Self {
         self.stdout = Some(stdout);
@@ -86,34 +373,229 @@ impl SafeCommand {
         self
     }
 
+    /// Kill the command if it has not finished after `timeout`. Only used
+    /// by [`SafeCommand::output`].
+    #[must_use]
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    /// Accept at most `max_output` bytes of stdout, failing with
+    /// `OutputTooLarge` beyond that, and keep as much of stderr, dropping
+    /// the rest. Only used by [`SafeCommand::output`].
+    #[must_use]
+    pub fn max_output(mut self, max_output: usize) -> Self {
+        self.max_output = max_output;
+        self
+    }
+
+    /// Write `input` to the child's stdin and close it. Without it,
+    /// [`SafeCommand::output`] gives the child a closed stdin so it cannot
+    /// wait for a prompt.
+    #[must_use]
+    pub fn input(mut self, input: &[u8]) -> Self {
+        self.input = Some(Zeroizing::new(input.to_vec()));
+        self
+    }
+
+    /// Run the child in `sandbox`: its own process group, no privilege
+    /// gain, killed when the app dies and under resource limits. Only
+    /// enforced on Linux.
+    #[must_use]
+    pub fn sandbox(mut self, sandbox: Sandbox) -> Self {
+        self.sandbox = Some(sandbox);
+        self
+    }
+
+    /// Run the command to completion, capturing its output.
+    ///
+    /// # Errors
+    /// Fails with `TimedOut` when the command outlives its timeout, after
+    /// killing it, with `OutputTooLarge` when stdout exceeds the output
+    /// limit, and with `Io` when its pipes or status cannot be read. stderr
+    /// over the limit is cut off with a marker instead of failing.
+    #[track_caller]
     pub fn output(self) -> Result<Output, SafeCommandError> {
-        let mut cmd = Command::new(&self.command);
-        cmd.args(&self.args);
+        let mut audit = AuditContext::new(&self, Location::caller());
+        let result = self.run_output(&mut audit);
+        match &result {
+            Ok(output) => audit.finish("exited", Some(output.status), None),
+            Err(SafeCommandError::TimedOut(_)) => audit.finish("timed_out", None, None),
+            Err(e) => audit.finish("failed", None, Some(e.to_string())),
+        }
+        result
+    }
 
-        if let Some(ref dir) = self.working_dir {
-            cmd.current_dir(dir);
+    fn run_output(mut self, audit: &mut AuditContext) -> Result<Output, SafeCommandError> {
+        let timeout = self.timeout;
+        let max_output = self.max_output;
+        let input = self.input.take();
+
+        self.stdin = Some(if input.is_some() {
+            Stdio::piped()
+        } else {
+            self.stdin.take().unwrap_or_else(Stdio::null)
+        });
+        self.stdout = Some(Stdio::piped());
+        self.stderr = Some(Stdio::piped());
+
+        let mut cmd = self.build()?;
+        audit.set_program(&cmd);
+        let mut child = cmd
+            .spawn()
+            .map_err(|e| SafeCommandError::ExecutionFailed(e.to_string()))?;
+
+        let stdout = child.stdout.take().map(|out| capture(out, max_output));
+        let stderr = child.stderr.take().map(|err| capture(err, max_output));
+        let writer = input.zip(child.stdin.take()).map(|(input, mut stdin)| {
+            thread::spawn(move || match stdin.write_all(&input) {
+                Err(e) if e.kind() != io::ErrorKind::BrokenPipe => Err(e),
+                _ => Ok(()),
+            })
+        });
+
+        let deadline = Instant::now() + timeout;
+        let status = loop {
+            match child.try_wait() {
+                Ok(Some(status)) => break status,
+                Ok(None) if Instant::now() < deadline => thread::sleep(WAIT_POLL_INTERVAL),
+                Ok(None) => {
+                    let _ = child.kill();
+                    let _ = child.wait();
+                    return Err(SafeCommandError::TimedOut(timeout));
+                }
+                Err(e) => {
+                    let _ = child.kill();
+                    let _ = child.wait();
+                    return Err(SafeCommandError::Io(e.to_string()));
+                }
+            }
+        };
+
+        if let Some(writer) = writer {
+            join_io(writer)?;
         }
+        let (stdout, stdout_dropped) = stdout.map(join_io).transpose()?.unwrap_or_default();
+        let (mut stderr, stderr_dropped) = stderr.map(join_io).transpose()?.unwrap_or_default();
 
-        if let Some(stdout) = self.stdout {
-            cmd.stdout(stdout);
+        // Cut-off stdout would be parsed as if complete, so it is an error.
+        if stdout_dropped > 0 {
+            return Err(SafeCommandError::OutputTooLarge(max_output));
+        }
+        if stderr_dropped > 0 {
+            stderr.extend_from_slice(format!("\n[truncated {stderr_dropped} bytes]\n").as_bytes());
         }
+        Ok(Output {
+            status,
+            stdout,
+            stderr,
+        })
+    }
 
-        if let Some(stderr) = self.stderr {
-            cmd.stderr(stderr);
+    /// Start the command without waiting for it, for processes that outlive
+    /// the current task. The audit log records the launch, and how the
+    /// process ended once it is reaped.
+    #[track_caller]
+    pub fn spawn(self) -> Result<SafeProcess, SafeCommandError> {
+        let mut audit = AuditContext::new(&self, Location::caller());
+        let result = self.build().and_then(|mut cmd| {
+            audit.set_program(&cmd);
+            cmd.spawn()
+                .map_err(|e| SafeCommandError::ExecutionFailed(e.to_string()))
+        });
+        match result {
+            Ok(child) => {
+                audit.clone().finish("spawned", None, None);
+                Ok(SafeProcess {
+                    child,
+                    audit: Some(Box::new(audit)),
+                })
+            }
+            Err(e) => {
+                audit.finish("failed", None, Some(e.to_string()));
+                Err(e)
+            }
+        }
+    }
+
+    /// Spawn the command on the tokio runtime. stdin is closed and stdout
+    /// and stderr are piped unless set otherwise, and the child is killed
+    /// if the returned handle is dropped before it exits.
+    ///
+    /// # Errors
+    /// Fails like [`SafeCommand::spawn`]; must be called within a tokio
+    /// runtime.
+    #[track_caller]
+    pub fn spawn_async(mut self) -> Result<SafeChild, SafeCommandError> {
+        let mut audit = AuditContext::new(&self, Location::caller());
+        self.stdin.get_or_insert_with(Stdio::null);
+        self.stdout.get_or_insert_with(Stdio::piped);
+        self.stderr.get_or_insert_with(Stdio::piped);
+
+        let result = self.build().and_then(|cmd| {
+            audit.set_program(&cmd);
+            tokio::process::Command::from(cmd)
+                .kill_on_drop(true)
+                .spawn()
+                .map_err(|e| SafeCommandError::ExecutionFailed(e.to_string()))
+        });
+        match result {
+            Ok(child) => Ok(SafeChild {
+                child,
+                audit: Some(Box::new(audit)),
+            }),
+            Err(e) => {
+                audit.finish("failed", None, Some(e.to_string()));
+                Err(e)
+            }
         }
+    }
 
-        cmd.output()
-            .map_err(|e| SafeCommandError::ExecutionFailed(e.to_string()))
+    /// The arguments as written to the audit log: the value following a
+    /// secret flag or option name of the policy is masked.
+    fn redacted_args(&self) -> Vec<String> {
+        let mut redacted = Vec::with_capacity(self.args.len());
+        let mut secret_next = false;
+        for arg in &self.args {
+            let Arg::Plain(arg) = arg else {
+                redacted.push(REDACTED.to_string());
+                continue;
+            };
+            if std::mem::take(&mut secret_next) {
+                redacted.push(REDACTED.to_string());
+                continue;
+            }
+            match arg.split_once('=') {
+                Some((flag, _)) if flag.starts_with('-') && self.policy.is_secret_key(flag) => {
+                    redacted.push(format!("{flag}={REDACTED}"));
+                }
+                _ => {
+                    secret_next = self.policy.is_secret_key(arg);
+                    redacted.push(arg.clone());
+                }
+            }
+        }
+        redacted
     }
 
-    pub fn spawn(self) -> Result<Child, SafeCommandError> {
-        let mut cmd = Command::new(&self.command);
-        cmd.args(&self.args);
+    fn build(self) -> Result<Command, SafeCommandError> {
+        self.check_complete()?;
+        let program = command_path::resolve(&self.command, self.policy.name)
+            .map_err(SafeCommandError::UntrustedExecutable)?;
+        let mut cmd = Command::new(program);
+        cmd.args(self.args.iter().map(Arg::expose));
 
         if let Some(ref dir) = self.working_dir {
             cmd.current_dir(dir);
         }
 
+        self.apply_env(&mut cmd);
+
+        if let Some(stdin) = self.stdin {
+            cmd.stdin(stdin);
+        }
+
         if let Some(stdout) = self.stdout {
             cmd.stdout(stdout);
         }
@@ -122,12 +604,240 @@ impl SafeCommand {
             cmd.stderr(stderr);
         }
 
-        cmd.spawn()
-            .map_err(|e| SafeCommandError::ExecutionFailed(e.to_string()))
+        if let Some(sandbox) = self.sandbox {
+            sandbox::apply(&mut cmd, sandbox);
+        }
+
+        Ok(cmd)
+    }
+
+    fn apply_env(&self, cmd: &mut Command) {
+        cmd.env_clear();
+        if self.inherit_env {
+            cmd.envs(std::env::vars_os().filter(|(key, _)| {
+                key.to_str().is_some_and(|key| {
+                    is_inherited(key) && !self.removed_envs.iter().any(|r| r == key)
+                })
+            }));
+        }
+        cmd.envs(self.envs.iter().map(|(k, v)| (k, v.as_str())));
+    }
+}
+
+/// A child started by [`SafeCommand::spawn_async`]. Its audit record is
+/// written once it has been waited for, or when it is killed on drop.
+pub struct SafeChild {
+    child: tokio::process::Child,
+    audit: Option<Box<AuditContext>>,
+}
+
+impl SafeChild {
+    /// The OS process id, until the child has been reaped.
+    #[must_use]
+    pub fn id(&self) -> Option<u32> {
+        self.child.id()
+    }
+
+    /// Lines of stdout, if it was piped and not taken yet.
+    pub fn stdout_lines(&mut self) -> Option<Lines<BufReader<ChildStdout>>> {
+        self.child.stdout.take().map(|out| BufReader::new(out).lines())
+    }
+
+    /// Lines of stderr, if it was piped and not taken yet.
+    pub fn stderr_lines(&mut self) -> Option<Lines<BufReader<ChildStderr>>> {
+        self.child.stderr.take().map(|err| BufReader::new(err).lines())
+    }
+
+    /// # Errors
+    /// Returns an error if the status cannot be queried.
+    pub fn try_wait(&mut self) -> Result<Option<ExitStatus>, SafeCommandError> {
+        let status = self
+            .child
+            .try_wait()
+            .map_err(|e| SafeCommandError::Io(e.to_string()))?;
+        if let Some(status) = status {
+            self.finish_audit("exited", Some(status));
+        }
+        Ok(status)
+    }
+
+    /// Ask the OS to kill the child without waiting for it.
+    pub fn start_kill(&mut self) {
+        let _ = self.child.start_kill();
+    }
+
+    /// # Errors
+    /// Returns an error if the child cannot be waited for.
+    pub async fn wait(&mut self) -> Result<ExitStatus, SafeCommandError> {
+        let status = self
+            .child
+            .wait()
+            .await
+            .map_err(|e| SafeCommandError::Io(e.to_string()))?;
+        self.finish_audit("exited", Some(status));
+        Ok(status)
+    }
+
+    /// Wait for the child to exit, killing it once `timeout` has passed.
+    ///
+    /// # Errors
+    /// Returns `TimedOut` after killing the child, or an error if it cannot
+    /// be waited for.
+    pub async fn wait_with_timeout(
+        &mut self,
+        timeout: Duration,
+    ) -> Result<ExitStatus, SafeCommandError> {
+        match tokio::time::timeout(timeout, self.child.wait()).await {
+            Ok(status) => {
+                let status = status.map_err(|e| SafeCommandError::Io(e.to_string()))?;
+                self.finish_audit("exited", Some(status));
+                Ok(status)
+            }
+            Err(_) => {
+                let _ = self.child.kill().await;
+                self.finish_audit("timed_out", None);
+                Err(SafeCommandError::TimedOut(timeout))
+            }
+        }
+    }
+
+    fn finish_audit(&mut self, outcome: &str, status: Option<ExitStatus>) {
+        if let Some(audit) = self.audit.take() {
+            audit.finish(outcome, status, None);
+        }
+    }
+}
+
+impl Drop for SafeChild {
+    fn drop(&mut self) {
+        self.finish_audit("killed", None);
+    }
+}
+
+/// A child started by [`SafeCommand::spawn`]. Its exit is written to the
+/// audit log once it has been reaped; it is killed if dropped before then.
+pub struct SafeProcess {
+    child: Child,
+    audit: Option<Box<AuditContext>>,
+}
+
+impl SafeProcess {
+    /// stderr, if it was piped and not taken yet.
+    pub fn take_stderr(&mut self) -> Option<std::process::ChildStderr> {
+        self.child.stderr.take()
+    }
+
+    /// # Errors
+    /// Returns an error if the status cannot be queried.
+    pub fn try_wait(&mut self) -> Result<Option<ExitStatus>, SafeCommandError> {
+        let status = self
+            .child
+            .try_wait()
+            .map_err(|e| SafeCommandError::Io(e.to_string()))?;
+        if let Some(status) = status {
+            self.finish_audit("exited", Some(status));
+        }
+        Ok(status)
+    }
+
+    /// Kill the child, unless it already exited, and reap it.
+    pub fn kill(&mut self) {
+        if let Ok(Some(_)) = self.try_wait() {
+            return;
+        }
+        let _ = self.child.kill();
+        let status = self.child.wait().ok();
+        self.finish_audit("killed", status);
+    }
+
+    fn finish_audit(&mut self, outcome: &str, status: Option<ExitStatus>) {
+        if let Some(audit) = self.audit.take() {
+            audit.finish(outcome, status, None);
+        }
+    }
+}
+
+impl Drop for SafeProcess {
+    fn drop(&mut self) {
+        if self.audit.is_some() {
+            self.kill();
+        }
+    }
+}
+
+/// What the audit log needs to know about a command, taken before it runs.
+#[derive(Clone)]
+struct AuditContext {
+    program: String,
+    args: Vec<String>,
+    caller: &'static Location<'static>,
+    timestamp: chrono::DateTime<chrono::Utc>,
+    started: Instant,
+}
+
+impl AuditContext {
+    fn new(cmd: &SafeCommand, caller: &'static Location<'static>) -> Self {
+        Self {
+            program: cmd.command.clone(),
+            args: cmd.redacted_args(),
+            caller,
+            timestamp: chrono::Utc::now(),
+            started: Instant::now(),
+        }
+    }
+
+    /// Record the resolved executable instead of the requested command.
+    fn set_program(&mut self, cmd: &Command) {
+        self.program = cmd.get_program().to_string_lossy().to_string();
+    }
+
+    fn finish(self, outcome: &str, status: Option<ExitStatus>, error: Option<String>) {
+        audit::record(&AuditRecord {
+            timestamp: self.timestamp.to_rfc3339(),
+            program: self.program,
+            args: self.args,
+            caller: format!("{}:{}", self.caller.file(), self.caller.line()),
+            outcome: outcome.to_string(),
+            exit_code: status.and_then(|status| status.code()),
+            duration_ms: u64::try_from(self.started.elapsed().as_millis()).unwrap_or(u64::MAX),
+            error,
+        });
     }
 }
 
-fn validate_argument(arg: &str) -> Result<(), SafeCommandError> {
+fn is_inherited(key: &str) -> bool {
+    let key = key.to_ascii_uppercase();
+    INHERITED_ENV.contains(&key.as_str())
+        || INHERITED_ENV_PREFIXES
+            .iter()
+            .any(|prefix| key.starts_with(prefix))
+}
+
+/// Read a pipe to the end on its own thread, keeping the first `max` bytes
+/// and counting how many were dropped.
+fn capture<R: Read + Send + 'static>(
+    mut reader: R,
+    max: usize,
+) -> JoinHandle<io::Result<(Vec<u8>, u64)>> {
+    thread::spawn(move || {
+        let mut captured = Vec::new();
+        reader
+            .by_ref()
+            .take(max as u64)
+            .read_to_end(&mut captured)?;
+        let dropped = io::copy(&mut reader, &mut io::sink())?;
+        Ok((captured, dropped))
+    })
+}
+
+fn join_io<T>(handle: JoinHandle<io::Result<T>>) -> Result<T, SafeCommandError> {
+    handle
+        .join()
+        .map_err(|_| SafeCommandError::Io("I/O thread panicked".to_string()))?
+        .map_err(|e| SafeCommandError::Io(e.to_string()))
+}
+
+fn validate_argument(arg: &str, forbidden: fn(char) -> bool) -> Result<(), SafeCommandError> {
     if arg.is_empty() {
         return Err(SafeCommandError::InvalidArgument(
             "Empty argument".to_string(),
@@ -140,23 +850,11 @@ fn validate_argument(arg: &str) -> Result<(), SafeCommandError> {
         ));
     }
 
-    for c in arg.chars() {
-        if FORBIDDEN_SHELL_CHARS.contains(&c) {
-            return Err(SafeCommandError::ShellInjectionAttempt(format!(
-                "Forbidden character '{}' in argument",
-                c.escape_default()
-            )));
-        }
-    }
-
-    let dangerous_patterns = ["$(", "`", "&&", "||", ">>", "<<"];
-
-    for pattern in dangerous_patterns {
-        if arg.contains(pattern) {
-            return Err(SafeCommandError::ShellInjectionAttempt(format!(
-                "Dangerous pattern '{pattern}' detected"
-            )));
-        }
+    if let Some(c) = arg.chars().find(|&c| forbidden(c)) {
+        return Err(SafeCommandError::ShellInjectionAttempt(format!(
+            "Forbidden character '{}' in argument",
+            c.escape_default()
+        )));
     }
 
     Ok(())
@@ -178,6 +876,7 @@ mod tests {
         assert!(SafeCommand::new("rm").is_err());
         assert!(SafeCommand::new("bash").is_err());
         assert!(SafeCommand::new("sh").is_err());
+        assert!(SafeCommand::new("bin/rclone").is_err());
     }
 
     #[test]
@@ -194,16 +893,398 @@ mod tests {
 
     #[test]
     fn test_injection_attempts() {
-        let cmd = SafeCommand::new("rclone").unwrap();
-        assert!(cmd.arg("; rm -rf /").is_err());
+        // No shell is involved, so metacharacters are plain data that reach
+        // rclone verbatim, as the path they spell.
+        for payload in ["; rm -rf /", "$(whoami)", "test`id`", "a && b"] {
+            let cmd = SafeCommand::new("rclone")
+                .unwrap()
+                .arg("sync")
+                .and_then(|c| c.arg(payload))
+                .unwrap();
+            assert_eq!(cmd.args.last().unwrap().expose(), payload);
 
-        let cmd = SafeCommand::new("rclone").unwrap();
-        assert!(cmd.arg("$(whoami)").is_err());
+            assert!(matches!(
+                SafeCommand::new("rclone").unwrap().arg(payload),
+                Err(SafeCommandError::SubcommandNotAllowed(_))
+            ));
+        }
+
+        // What the policy does stop: line breaks, unlisted options, and
+        // options smuggled into a value.
+        let sync = || SafeCommand::new("rclone").unwrap().arg("sync").unwrap();
+        assert!(matches!(
+            sync().arg("a\nb"),
+            Err(SafeCommandError::ShellInjectionAttempt(_))
+        ));
+        assert!(matches!(
+            sync().arg("--config=/tmp/evil.conf"),
+            Err(SafeCommandError::FlagNotAllowed(_))
+        ));
+        assert!(sync()
+            .arg("--transfers")
+            .and_then(|c| c.arg("--config"))
+            .is_err());
+    }
+
+    #[test]
+    fn test_notify_send_end_of_options() {
+        let cmd = SafeCommand::new("notify-send")
+            .unwrap()
+            .arg("--")
+            .and_then(|c| c.arg("-1 new file"))
+            .and_then(|c| c.arg("--urgency=critical"))
+            .unwrap();
+        assert_eq!(cmd.args.len(), 3);
+
+        assert!(matches!(
+            SafeCommand::new("notify-send").unwrap().arg("-1 new file"),
+            Err(SafeCommandError::FlagNotAllowed(_))
+        ));
+        assert!(SafeCommand::new("rclone")
+            .unwrap()
+            .arg("sync")
+            .and_then(|c| c.arg("--"))
+            .is_err());
+    }
+
+    #[test]
+    fn test_rclone_policy() {
+        let cmd = SafeCommand::new("rclone")
+            .unwrap()
+            .arg("sync")
+            .and_then(|c| c.arg("/data/{a,b} (copy)"))
+            .and_then(|c| c.arg("remote:bucket"))
+            .and_then(|c| c.arg("--transfers"))
+            .and_then(|c| c.arg("8"))
+            .and_then(|c| c.arg("--combined=-"));
+        assert!(cmd.is_ok());
+
+        let cmd = SafeCommand::new("rclone").unwrap().arg("sync").unwrap();
+        assert!(matches!(
+            cmd.arg("--config"),
+            Err(SafeCommandError::FlagNotAllowed(_))
+        ));
+
+        let cmd = SafeCommand::new("rclone").unwrap().arg("sync").unwrap();
+        assert!(cmd.arg("--transfers=many").is_err());
 
         let cmd = SafeCommand::new("rclone").unwrap();
-        assert!(cmd.arg("test`id`").is_err());
+        assert!(matches!(
+            cmd.arg("serve"),
+            Err(SafeCommandError::SubcommandNotAllowed(_))
+        ));
 
         let cmd = SafeCommand::new("rclone").unwrap();
-        assert!(cmd.arg("a && b").is_err());
+        assert!(cmd.arg("sync\n").is_err());
+    }
+
+    #[test]
+    fn test_incomplete_command() {
+        let cmd = SafeCommand::new("rclone")
+            .unwrap()
+            .arg("config")
+            .unwrap()
+            .arg("encryption")
+            .unwrap();
+        assert!(matches!(
+            cmd.check_complete(),
+            Err(SafeCommandError::SubcommandNotAllowed(_))
+        ));
+
+        let cmd = SafeCommand::new("rclone")
+            .unwrap()
+            .arg("rcd")
+            .unwrap()
+            .arg("--rc-addr")
+            .unwrap();
+        assert!(matches!(
+            cmd.check_complete(),
+            Err(SafeCommandError::MissingFlagValue(_))
+        ));
+    }
+
+    #[test]
+    fn test_capture_truncates() {
+        let (captured, dropped) =
+            join_io(capture(io::Cursor::new(b"0123456789".to_vec()), 4)).unwrap();
+        assert_eq!((captured.as_slice(), dropped), (&b"0123"[..], 6));
+
+        let (captured, dropped) = join_io(capture(io::Cursor::new(b"0123".to_vec()), 4)).unwrap();
+        assert_eq!((captured.as_slice(), dropped), (&b"0123"[..], 0));
+    }
+
+    #[test]
+    fn test_inherited_env() {
+        assert!(is_inherited("PATH"));
+        assert!(is_inherited("LC_ALL"));
+        assert!(is_inherited("XDG_RUNTIME_DIR"));
+        assert!(is_inherited("https_proxy"));
+        assert!(!is_inherited("RCLONE_CONFIG"));
+        assert!(!is_inherited("AWS_SECRET_ACCESS_KEY"));
+    }
+
+    #[test]
+    fn test_env_overrides() {
+        let cmd = SafeCommand::new("rclone")
+            .unwrap()
+            .env("RCLONE_CONFIG_PASS", "first")
+            .and_then(|c| c.env("RCLONE_CONFIG_PASS", "second"))
+            .and_then(|c| c.env("RCLONE_ASK_PASSWORD", "false"))
+            .unwrap()
+            .env_remove("RCLONE_ASK_PASSWORD")
+            .env_remove("HOME");
+
+        let mut command = Command::new("rclone");
+        cmd.apply_env(&mut command);
+        let envs: Vec<_> = command.get_envs().collect();
+        assert!(envs.contains(&("RCLONE_CONFIG_PASS".as_ref(), Some("second".as_ref()))));
+        assert!(!envs.iter().any(|(k, _)| *k == "RCLONE_ASK_PASSWORD" || *k == "HOME"));
+
+        let cleared = SafeCommand::new("rclone")
+            .unwrap()
+            .env("RCLONE_CONFIG_PASS", "secret")
+            .unwrap()
+            .env_clear();
+        let mut command = Command::new("rclone");
+        cleared.apply_env(&mut command);
+        assert_eq!(command.get_envs().count(), 0);
+    }
+
+    #[test]
+    fn test_current_dir() {
+        let dir = std::env::temp_dir().join(format!(
+            "botapp-safe-command-{}",
+            uuid::Uuid::new_v4().simple()
+        ));
+        std::fs::create_dir(&dir).unwrap();
+
+        let cmd = SafeCommand::new("rclone").unwrap().current_dir(&dir);
+        assert!(cmd.is_ok());
+
+        let cmd = SafeCommand::new("rclone")
+            .unwrap()
+            .current_dir(&dir.join("missing"));
+        assert!(matches!(cmd, Err(SafeCommandError::InvalidWorkingDir(_))));
+
+        std::fs::remove_dir(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_redacted_args() {
+        let cmd = SafeCommand::new("rclone")
+            .unwrap()
+            .arg("config")
+            .and_then(|c| c.arg("update"))
+            .and_then(|c| c.arg("backup"))
+            .and_then(|c| c.arg("secret_access_key"))
+            .and_then(|c| c.arg("hunter2"))
+            .and_then(|c| c.arg("region"))
+            .and_then(|c| c.arg("eu-west-1"))
+            .unwrap();
+        assert_eq!(
+            cmd.redacted_args(),
+            [
+                "config",
+                "update",
+                "backup",
+                "secret_access_key",
+                REDACTED,
+                "region",
+                "eu-west-1"
+            ]
+        );
+    }
+
+    #[test]
+    fn test_arg_secret() {
+        let cmd = SafeCommand::new("rclone")
+            .unwrap()
+            .arg("obscure")
+            .and_then(|c| c.arg_secret("hunter2"))
+            .unwrap();
+        assert_eq!(cmd.args.len(), 2);
+        assert_eq!(cmd.args[1].expose(), "-");
+        assert_eq!(cmd.input.as_deref().map(Vec::as_slice), Some(&b"hunter2"[..]));
+
+        let long = format!("-{}\t", "x".repeat(5000));
+        let cmd = SafeCommand::new("rclone")
+            .unwrap()
+            .arg("obscure")
+            .and_then(|c| c.arg_secret(&long))
+            .unwrap();
+        assert_eq!(cmd.input.as_deref().map(Vec::as_slice), Some(long.as_bytes()));
+
+        assert!(SafeCommand::new("rclone")
+            .unwrap()
+            .arg("config")
+            .and_then(|c| c.arg("update"))
+            .and_then(|c| c.arg("backup"))
+            .and_then(|c| c.arg("session_token"))
+            .and_then(|c| c.arg_secret("-hunter2"))
+            .is_err());
+
+        let cmd = SafeCommand::new("rclone")
+            .unwrap()
+            .arg("config")
+            .and_then(|c| c.arg("update"))
+            .and_then(|c| c.arg("backup"))
+            .and_then(|c| c.arg("session_token"))
+            .and_then(|c| c.arg_secret("hunter2"))
+            .unwrap();
+        assert_eq!(cmd.args[4].expose(), "hunter2");
+        assert!(!format!("{cmd:?}").contains("hunter2"));
+        assert_eq!(cmd.redacted_args()[4], REDACTED);
+
+        let cmd = SafeCommand::new("rclone")
+            .unwrap()
+            .arg("sync")
+            .and_then(|c| c.arg("--bwlimit"))
+            .and_then(|c| c.arg_secret("10M"))
+            .unwrap();
+        assert_eq!(cmd.args.len(), 1);
+        assert!(cmd.envs.iter().any(|(k, v)| k == "RCLONE_BWLIMIT" && **v == "10M"));
+
+        let err = SafeCommand::new("rclone")
+            .unwrap()
+            .arg_secret("hunter2")
+            .unwrap_err();
+        assert!(!err.to_string().contains("hunter2"));
+    }
+
+    #[test]
+    fn test_osascript_policy() {
+        let cmd = SafeCommand::new("osascript")
+            .unwrap()
+            .arg("-e")
+            .unwrap()
+            .arg(r#"display notification "Done" with title "Sync""#);
+        assert!(cmd.is_ok());
+
+        let cmd = SafeCommand::new("osascript").unwrap().arg("-e").unwrap();
+        assert!(cmd.arg(r#"do shell script "id""#).is_err());
+
+        let cmd = SafeCommand::new("osascript").unwrap();
+        assert!(cmd.arg("/tmp/script.scpt").is_err());
+    }
+
+    /// Property tests for the argument checks. Commands never run through a
+    /// shell, so the threat is not metacharacters but arguments that change
+    /// what an allowed program does. Whatever the input, checking must
+    /// guarantee that:
+    ///
+    /// - it never panics;
+    /// - every accepted argument is non-empty and at most 4096 bytes;
+    /// - no accepted argument contains a character the policy forbids:
+    ///   control characters for rclone and osascript, NUL for notify-send,
+    ///   so no argument can carry line breaks into config files, logs or
+    ///   scripts;
+    /// - accepted arguments reach the child verbatim, so shell
+    ///   metacharacters and any Unicode text are inert data;
+    /// - a flag the policy does not list is rejected however it is spelled,
+    ///   unless it follows a `--` the policy lists, where it is plain text;
+    /// - osascript only ever gets a single `display notification` script.
+    ///
+    /// What a program does with a valid argument, such as the remote path
+    /// rclone syncs to, is up to the caller and not covered here.
+    mod properties {
+        use crate::desktop::command_policy::applescript_string;
+        use super::*;
+        use proptest::prelude::*;
+
+        fn rclone_sync() -> SafeCommand {
+            SafeCommand::new("rclone").unwrap().arg("sync").unwrap()
+        }
+
+        fn last_arg(cmd: &SafeCommand) -> &str {
+            cmd.args.last().map(Arg::expose).unwrap_or_default()
+        }
+
+        /// Positional arguments, which never start with `-`.
+        fn positional() -> impl Strategy<Value = String> {
+            any::<String>().prop_filter("not a flag", |s| !s.starts_with('-'))
+        }
+
+        proptest! {
+            #[test]
+            fn accepted_arguments_keep_the_guarantees(arg in positional()) {
+                let Ok(cmd) = rclone_sync().arg(&arg) else {
+                    return Ok(());
+                };
+                prop_assert!(!arg.is_empty() && arg.len() <= 4096);
+                prop_assert!(!arg.chars().any(char::is_control));
+                prop_assert_eq!(last_arg(&cmd), arg.as_str());
+            }
+
+            #[test]
+            fn printable_unicode_is_accepted_verbatim(arg in "[^-\\p{Cc}]\\PC{0,300}") {
+                let cmd = rclone_sync().arg(&arg).unwrap();
+                prop_assert_eq!(last_arg(&cmd), arg.as_str());
+            }
+
+            #[test]
+            fn shell_metacharacters_are_inert(arg in "[;&|$`()<>*?!{}\\[\\]'\"\\\\ ~#%=a-z/]{1,64}") {
+                let cmd = rclone_sync().arg(&arg).unwrap();
+                prop_assert_eq!(last_arg(&cmd), arg.as_str());
+
+                let cmd = SafeCommand::new("notify-send").unwrap().arg(&arg).unwrap();
+                prop_assert_eq!(last_arg(&cmd), arg.as_str());
+            }
+
+            #[test]
+            fn control_characters_are_rejected(
+                prefix in "\\PC{0,32}",
+                control in "\\p{Cc}",
+                suffix in "\\PC{0,32}",
+            ) {
+                let arg = format!("{prefix}{control}{suffix}");
+                let flag_value = format!("--bwlimit={arg}");
+                prop_assert!(rclone_sync().arg(&arg).is_err());
+                prop_assert!(rclone_sync().arg(&flag_value).is_err());
+
+                let osascript = SafeCommand::new("osascript").unwrap().arg("-e").unwrap();
+                prop_assert!(osascript.arg(&arg).is_err());
+
+                let nul = format!("{prefix}\0{suffix}");
+                prop_assert!(SafeCommand::new("notify-send").unwrap().arg(&nul).is_err());
+            }
+
+            #[test]
+            fn long_arguments_are_rejected(arg in "[a-z]{4097,5000}|é{2049,2500}") {
+                prop_assert!(rclone_sync().arg(&arg).is_err());
+                prop_assert!(SafeCommand::new("notify-send").unwrap().arg(&arg).is_err());
+            }
+
+            #[test]
+            fn unlisted_flags_are_rejected(flag in "--?[a-z][a-z-]{0,31}", value in "(=\\PC{0,16})?") {
+                let policy = policy_for("rclone").unwrap();
+                prop_assume!(!policy.flags.contains(&flag.as_str()));
+                prop_assume!(policy.value_flag(&flag).is_none());
+
+                let arg = format!("{flag}{value}");
+                let result = rclone_sync().arg(&arg);
+                prop_assert!(matches!(result, Err(SafeCommandError::FlagNotAllowed(_))));
+            }
+
+            #[test]
+            fn osascript_runs_only_notifications(
+                body in any::<String>(),
+                title in any::<String>(),
+                tail in any::<String>(),
+            ) {
+                let script = format!(
+                    "display notification {} with title {}",
+                    applescript_string(&body),
+                    applescript_string(&title)
+                );
+                prop_assume!(script.len() <= 4096);
+
+                let osascript = || SafeCommand::new("osascript").unwrap().arg("-e").unwrap();
+                prop_assert!(osascript().arg(&script).is_ok());
+                if !tail.is_empty() {
+                    let extended = format!("{script}{tail}");
+                    prop_assert!(osascript().arg(&extended).is_err());
+                }
+            }
+        }
     }
 }
@@ -0,0 +1,134 @@
+use std::process::Command;
+
+/// Restrictions for a child started by `SafeCommand`. On Linux the child
+/// runs in its own process group, cannot gain privileges through setuid
+/// binaries or file capabilities, is killed when the app dies, and gets the
+/// resource limits below. Elsewhere the sandbox is ignored.
+///
+/// The kill on exit follows the thread that spawned the child, so sandboxed
+/// commands must be spawned from threads that live as long as the app, such
+/// as the async runtime workers, not from `spawn_blocking`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Sandbox {
+    /// CPU time in seconds before the kernel kills the child.
+    pub cpu_seconds: Option<u64>,
+    /// Address space in bytes.
+    pub memory_bytes: Option<u64>,
+    /// Open file descriptors.
+    pub open_files: Option<u64>,
+}
+
+impl Sandbox {
+    /// rclone transfers and the rc daemon run for hours, so only memory and
+    /// file descriptors are bounded.
+    pub const RCLONE: Self = Self {
+        cpu_seconds: None,
+        memory_bytes: Some(8 * 1024 * 1024 * 1024),
+        open_files: Some(4096),
+    };
+
+    /// Short-lived helpers such as desktop notifiers.
+    pub const HELPER: Self = Self {
+        cpu_seconds: Some(10),
+        memory_bytes: Some(1024 * 1024 * 1024),
+        open_files: Some(256),
+    };
+}
+
+/// Set up `cmd` to start in `sandbox`.
+#[cfg(target_os = "linux")]
+#[allow(unsafe_code)]
+pub fn apply(cmd: &mut Command, sandbox: Sandbox) {
+    use std::os::unix::process::CommandExt;
+
+    let parent = std::process::id();
+    cmd.process_group(0);
+    // SAFETY: `restrict` runs between fork and exec and only makes
+    // async-signal-safe system calls, without allocating.
+    unsafe {
+        cmd.pre_exec(move || restrict(parent, sandbox));
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+pub fn apply(_cmd: &mut Command, _sandbox: Sandbox) {}
+
+#[cfg(target_os = "linux")]
+#[allow(unsafe_code)]
+fn restrict(parent: u32, sandbox: Sandbox) -> std::io::Result<()> {
+    // SAFETY: plain system calls on the child's own process state.
+    unsafe {
+        check(libc::prctl(libc::PR_SET_NO_NEW_PRIVS, 1, 0, 0, 0))?;
+        check(libc::prctl(libc::PR_SET_PDEATHSIG, libc::SIGKILL, 0, 0, 0))?;
+
+        // The app may have died before the signal was armed.
+        if u32::try_from(libc::getppid()).ok() != Some(parent) {
+            return Err(std::io::Error::from_raw_os_error(libc::ESRCH));
+        }
+
+        let limits = [
+            (libc::RLIMIT_CPU, sandbox.cpu_seconds),
+            (libc::RLIMIT_AS, sandbox.memory_bytes),
+            (libc::RLIMIT_NOFILE, sandbox.open_files),
+        ];
+        for (resource, limit) in limits {
+            let Some(limit) = limit else { continue };
+            let mut current = libc::rlimit {
+                rlim_cur: 0,
+                rlim_max: 0,
+            };
+            check(libc::getrlimit(resource, &mut current))?;
+            // Never above the hard limit, which only root may raise.
+            let value = libc::rlim_t::try_from(limit)
+                .unwrap_or(libc::RLIM_INFINITY)
+                .min(current.rlim_max);
+            let rlimit = libc::rlimit {
+                rlim_cur: value,
+                rlim_max: value,
+            };
+            check(libc::setrlimit(resource, &rlimit))?;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(target_os = "linux")]
+fn check(result: libc::c_int) -> std::io::Result<()> {
+    if result == -1 {
+        Err(std::io::Error::last_os_error())
+    } else {
+        Ok(())
+    }
+}
+
+#[cfg(all(test, target_os = "linux"))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_linux_sandbox() {
+        let mut cmd = Command::new("/bin/sh");
+        cmd.arg("-c").arg(
+            "ulimit -n; ulimit -t; grep NoNewPrivs /proc/self/status; \
+             echo $$; cut -d' ' -f5 /proc/$$/stat",
+        );
+        apply(
+            &mut cmd,
+            Sandbox {
+                cpu_seconds: Some(5),
+                memory_bytes: None,
+                open_files: Some(64),
+            },
+        );
+        let output = cmd.output().unwrap();
+        assert!(output.status.success());
+
+        let stdout = String::from_utf8_lossy(&output.stdout);
+        let lines: Vec<&str> = stdout.lines().map(str::trim).collect();
+        assert_eq!(lines[0], "64");
+        assert_eq!(lines[1], "5");
+        assert!(lines[2].ends_with('1'));
+        // The shell leads its own process group.
+        assert_eq!(lines[3], lines[4]);
+    }
+}
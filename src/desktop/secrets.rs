@@ -0,0 +1,76 @@
+use super::storage;
+use std::collections::HashMap;
+
+const SERVICE_NAME: &str = "br.com.pragmatismo.botapp";
+const SECRETS_FILE: &str = "secrets.json";
+
+/// Environment variable selecting the secret backend. Set it to `file` on
+/// headless machines and in tests, where no OS secret service is running.
+pub const SECRET_BACKEND_ENV: &str = "BOTAPP_SECRET_BACKEND";
+
+fn use_file_backend() -> bool {
+    std::env::var(SECRET_BACKEND_ENV).is_ok_and(|v| v.eq_ignore_ascii_case("file"))
+}
+
+/// Store a secret in the OS secret service, or in the file fallback.
+///
+/// # Errors
+/// Returns an error if the secret cannot be written.
+pub fn set_secret(key: &str, value: &str) -> Result<(), String> {
+    if use_file_backend() {
+        let mut secrets = load_file_secrets();
+        secrets.insert(key.to_string(), value.to_string());
+        return save_file_secrets(&secrets);
+    }
+
+    keyring::Entry::new(SERVICE_NAME, key)
+        .and_then(|entry| entry.set_password(value))
+        .map_err(|e| format!("Failed to store secret {key}: {e}"))
+}
+
+/// Read a secret, returning `None` if it was never stored.
+///
+/// # Errors
+/// Returns an error if the secret store cannot be queried.
+pub fn get_secret(key: &str) -> Result<Option<String>, String> {
+    if use_file_backend() {
+        return Ok(load_file_secrets().remove(key));
+    }
+
+    let entry = keyring::Entry::new(SERVICE_NAME, key)
+        .map_err(|e| format!("Failed to open secret {key}: {e}"))?;
+    match entry.get_password() {
+        Ok(value) => Ok(Some(value)),
+        Err(keyring::Error::NoEntry) => Ok(None),
+        Err(e) => Err(format!("Failed to read secret {key}: {e}")),
+    }
+}
+
+/// Remove a secret. Removing a missing secret is not an error.
+///
+/// # Errors
+/// Returns an error if the secret store cannot be updated.
+pub fn delete_secret(key: &str) -> Result<(), String> {
+    if use_file_backend() {
+        let mut secrets = load_file_secrets();
+        if secrets.remove(key).is_some() {
+            save_file_secrets(&secrets)?;
+        }
+        return Ok(());
+    }
+
+    let entry = keyring::Entry::new(SERVICE_NAME, key)
+        .map_err(|e| format!("Failed to open secret {key}: {e}"))?;
+    match entry.delete_credential() {
+        Ok(()) | Err(keyring::Error::NoEntry) => Ok(()),
+        Err(e) => Err(format!("Failed to delete secret {key}: {e}")),
+    }
+}
+
+fn load_file_secrets() -> HashMap<String, String> {
+    storage::load_json(SECRETS_FILE)
+}
+
+fn save_file_secrets(secrets: &HashMap<String, String>) -> Result<(), String> {
+    storage::save_private_json(SECRETS_FILE, secrets)
+}
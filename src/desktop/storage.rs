@@ -0,0 +1,88 @@
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+use std::path::PathBuf;
+
+/// Environment variable that overrides the data directory, used by headless
+/// deployments and tests.
+pub const DATA_DIR_ENV: &str = "BOTAPP_DATA_DIR";
+
+/// Directory where botapp keeps its own state files.
+///
+/// # Errors
+/// Returns an error if the directory cannot be determined or created.
+pub fn app_data_dir() -> Result<PathBuf, String> {
+    let dir = match std::env::var_os(DATA_DIR_ENV) {
+        Some(dir) => PathBuf::from(dir),
+        None => dirs::data_local_dir()
+            .ok_or_else(|| "Could not determine data directory".to_string())?
+            .join("botapp"),
+    };
+
+    if !dir.exists() {
+        fs::create_dir_all(&dir).map_err(|e| format!("Failed to create data directory: {e}"))?;
+    }
+
+    Ok(dir)
+}
+
+/// Load a JSON state file from the data directory, falling back to the
+/// default value when it is missing or unreadable.
+pub fn load_json<T: DeserializeOwned + Default>(name: &str) -> T {
+    let Ok(path) = app_data_dir().map(|d| d.join(name)) else {
+        return T::default();
+    };
+
+    let Ok(content) = fs::read_to_string(&path) else {
+        return T::default();
+    };
+
+    serde_json::from_str(&content).unwrap_or_else(|e| {
+        log::warn!("Ignoring corrupt state file {}: {e}", path.display());
+        T::default()
+    })
+}
+
+/// Write a JSON state file to the data directory, replacing it atomically.
+///
+/// # Errors
+/// Returns an error if the value cannot be serialized or written.
+pub fn save_json<T: Serialize>(name: &str, value: &T) -> Result<(), String> {
+    write_json(name, value, false)
+}
+
+/// Like [`save_json`], but the file is only readable by the current user.
+///
+/// # Errors
+/// Returns an error if the value cannot be serialized or written.
+pub fn save_private_json<T: Serialize>(name: &str, value: &T) -> Result<(), String> {
+    write_json(name, value, true)
+}
+
+fn write_json<T: Serialize>(name: &str, value: &T, private: bool) -> Result<(), String> {
+    let path = app_data_dir()?.join(name);
+    let tmp_path = path.with_extension("tmp");
+
+    let content =
+        serde_json::to_string_pretty(value).map_err(|e| format!("Failed to serialize: {e}"))?;
+
+    let mut options = OpenOptions::new();
+    options.write(true).create_new(true);
+    #[cfg(unix)]
+    if private {
+        use std::os::unix::fs::OpenOptionsExt;
+        options.mode(0o600);
+    }
+    #[cfg(not(unix))]
+    let _ = private;
+
+    let _ = fs::remove_file(&tmp_path);
+    options
+        .open(&tmp_path)
+        .and_then(|mut file| file.write_all(content.as_bytes()))
+        .map_err(|e| format!("Failed to write {name}: {e}"))?;
+    fs::rename(&tmp_path, &path).map_err(|e| format!("Failed to write {name}: {e}"))?;
+
+    Ok(())
+}
@@ -1,11 +1,170 @@
-use super::safe_command::SafeCommand;
+use super::filters::{render_rules, write_filter_file, write_preview_filter_file};
+use super::history::{self, SyncRun};
+use super::provisioning::rclone_program;
+use super::rc::{self, JobStats, RcClient};
+use super::rclone::{rclone_command, RcloneCapabilities, RcloneVersion};
+use super::safe_command::{SafeChild, SafeCommand};
+use super::sandbox::Sandbox;
+use super::storage;
 use serde::{Deserialize, Serialize};
-use std::path::PathBuf;
-use std::process::{Child, Stdio};
-use std::sync::Mutex;
+use serde_json::{json, Value};
+use std::collections::{HashMap, VecDeque};
+use std::path::{Path, PathBuf};
+use std::process::ExitStatus;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
 use tauri::{Emitter, Window};
+use tokio::io::{AsyncBufRead, Lines};
+use tokio::task::JoinHandle;
 
-static RCLONE_PROCESS: Mutex<Option<Child>> = Mutex::new(None);
+static RCLONE_PROCESS: Mutex<Option<SyncProcess>> = Mutex::new(None);
+
+const PROFILES_FILE: &str = "sync_profiles.json";
+const LOG_TAIL_LINES: usize = 200;
+const MAX_RUN_ERRORS: usize = 20;
+const DEFAULT_STOP_GRACE_SECS: u64 = 30;
+const STOP_POLL_INTERVAL: Duration = Duration::from_millis(250);
+const MAX_TRANSFERS: u32 = 64;
+const MAX_CHECKERS: u32 = 256;
+/// `rclone check` walks both sides and lists every file, so previews get
+/// more time and output than other commands.
+const PREVIEW_TIMEOUT: Duration = Duration::from_secs(30 * 60);
+const PREVIEW_MAX_OUTPUT: usize = 64 * 1024 * 1024;
+
+/// Markers rclone bisync prints when its listings are missing or unusable and
+/// only a `--resync` run can recover.
+const RESYNC_REQUIRED_MARKERS: [&str; 2] = [
+    "must run --resync",
+    "cannot find prior path1 or path2 listings",
+];
+
+struct SyncProcess {
+    run_id: String,
+    started_at: chrono::DateTime<chrono::Utc>,
+    runner: Runner,
+    state: ProcessState,
+    config: SyncConfig,
+    filter_rules: Vec<String>,
+    output: Arc<Mutex<RunOutput>>,
+}
+
+/// What executes a sync: a one-shot rclone process whose output is read by
+/// `readers`, or a job of the shared `rclone rcd` daemon.
+enum Runner {
+    Process {
+        child: SafeChild,
+        readers: Vec<JoinHandle<()>>,
+    },
+    RemoteControl {
+        client: RcClient,
+        job_id: u64,
+    },
+}
+
+impl Runner {
+    fn signal(&self, signal: Signal) -> bool {
+        match self {
+            Self::Process { child, .. } => send_signal(child, signal),
+            Self::RemoteControl { .. } => false,
+        }
+    }
+
+    fn kill(&mut self) {
+        match self {
+            Self::Process { child, .. } => child.start_kill(),
+            Self::RemoteControl { .. } => rc::shutdown_daemon(),
+        }
+    }
+
+    /// Cancel a run that never got registered.
+    async fn abort(self) {
+        match self {
+            Self::Process { mut child, .. } => {
+                child.start_kill();
+                let _ = child.wait().await;
+            }
+            Self::RemoteControl { client, job_id } => {
+                let _ = client.stop_job(job_id).await;
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum ProcessState {
+    Running,
+    Paused,
+    Stopping,
+}
+
+impl ProcessState {
+    fn as_status(self) -> &'static str {
+        match self {
+            Self::Running => "syncing",
+            Self::Paused => "paused",
+            Self::Stopping => "stopping",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+enum Signal {
+    Interrupt,
+    Stop,
+    Continue,
+}
+
+/// What rclone reported so far: the log tail plus the counters from its
+/// JSON stats lines.
+#[derive(Debug, Default)]
+struct RunOutput {
+    tail: VecDeque<String>,
+    files_transferred: u64,
+    bytes_transferred: u64,
+    error_count: u64,
+    errors: Vec<String>,
+    current_file: Option<String>,
+}
+
+/// A line of rclone `--use-json-log` output.
+#[derive(Debug, Deserialize)]
+struct LogLine {
+    #[serde(default)]
+    time: String,
+    level: String,
+    msg: String,
+    #[serde(default)]
+    object: Option<String>,
+    #[serde(default)]
+    stats: Option<LogStats>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+struct LogStats {
+    #[serde(default)]
+    bytes: u64,
+    #[serde(default)]
+    transfers: u64,
+    #[serde(default)]
+    errors: u64,
+}
+
+enum RunOutcome {
+    Exited(ExitStatus),
+    JobFinished(Result<(), String>),
+    Stopped,
+    Failed(String),
+}
+
+/// Persisted per-profile state. `bisync_paths` and `bisync_filters` record
+/// the path pair and filter rules the profile's bisync listings were created
+/// for; bisync has to resync when either changes.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ProfileState {
+    pub bisync_paths: Option<String>,
+    #[serde(default)]
+    pub bisync_filters: Option<String>,
+}
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct SyncStatus {
@@ -20,20 +179,54 @@ pub struct SyncStatus {
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct SyncConfig {
+    #[serde(default = "default_profile")]
+    pub profile: String,
     pub local_path: String,
     pub remote_name: String,
     pub remote_path: String,
     pub sync_mode: SyncMode,
     pub exclude_patterns: Vec<String>,
+    #[serde(default)]
+    pub force_resync: bool,
+    /// rclone `--bwlimit` value: a rate such as `4M`, an upload:download pair
+    /// such as `1M:8M`, or a timetable such as `08:00,512k 19:00,off`.
+    #[serde(default)]
+    pub bandwidth_limit: Option<String>,
+    #[serde(default)]
+    pub transfers: Option<u32>,
+    #[serde(default)]
+    pub checkers: Option<u32>,
+    /// Only sync files newer than this rclone duration (`7d`, `1h30m`) or date.
+    #[serde(default)]
+    pub max_age: Option<String>,
+    #[serde(default)]
+    pub min_size: Option<String>,
+    #[serde(default)]
+    pub max_size: Option<String>,
+    #[serde(default)]
+    pub backend: SyncBackend,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+fn default_profile() -> String {
+    "default".to_string()
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
 pub enum SyncMode {
     Push,
     Pull,
     Bisync,
 }
 
+/// How `start_sync` drives rclone. `RemoteControl` runs syncs as jobs of a
+/// local `rclone rcd`, which reports progress and cancels through its API.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum SyncBackend {
+    #[default]
+    Process,
+    RemoteControl,
+}
+
 impl Default for SyncConfig {
     fn default() -> Self {
         let local_path = dirs::home_dir().map_or_else(
@@ -41,6 +234,7 @@ impl Default for SyncConfig {
             |p| p.join("GeneralBots").to_string_lossy().to_string(),
         );
         Self {
+            profile: default_profile(),
             local_path,
             remote_name: "gbdrive".to_string(),
             remote_path: "/".to_string(),
@@ -51,27 +245,80 @@ impl Default for SyncConfig {
                 "*.tmp".to_string(),
                 ".git/**".to_string(),
             ],
+            force_resync: false,
+            bandwidth_limit: None,
+            transfers: None,
+            checkers: None,
+            max_age: None,
+            min_size: None,
+            max_size: None,
+            backend: SyncBackend::Process,
         }
     }
 }
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SyncSide {
+    Local,
+    Remote,
+}
+
+/// A single file that a sync would touch. `target` is the side that gets
+/// modified, or `None` when rclone decides the direction at run time.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PreviewItem {
+    pub path: String,
+    pub target: Option<SyncSide>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SyncPreview {
+    pub sync_mode: SyncMode,
+    pub to_copy: Vec<PreviewItem>,
+    pub to_update: Vec<PreviewItem>,
+    pub to_delete: Vec<PreviewItem>,
+    pub unchanged: u64,
+    pub errors: Vec<String>,
+}
+
+impl SyncPreview {
+    fn new(sync_mode: SyncMode) -> Self {
+        Self {
+            sync_mode,
+            to_copy: Vec::new(),
+            to_update: Vec::new(),
+            to_delete: Vec::new(),
+            unchanged: 0,
+            errors: Vec::new(),
+        }
+    }
+
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.to_copy.is_empty() && self.to_update.is_empty() && self.to_delete.is_empty()
+    }
+}
+
 #[tauri::command]
 #[must_use]
 pub fn get_sync_status() -> SyncStatus {
     let process_guard = RCLONE_PROCESS
         .lock()
         .unwrap_or_else(std::sync::PoisonError::into_inner);
-    let is_running = process_guard.is_some();
+    let running = process_guard
+        .as_ref()
+        .map(|p| (Arc::clone(&p.output), p.state));
     drop(process_guard);
 
+    if let Some((output, state)) = running {
+        return progress_status(&output, state);
+    }
+
     SyncStatus {
-        status: if is_running {
-            "syncing".to_string()
-        } else {
-            "idle".to_string()
-        },
-        is_running,
-        last_sync: None,
+        status: "idle".to_string(),
+        is_running: false,
+        last_sync: history::last_run(None).map(|run| run.finished_at),
         files_synced: 0,
         bytes_transferred: 0,
         current_file: None,
@@ -80,8 +327,9 @@ pub fn get_sync_status() -> SyncStatus {
 }
 
 #[tauri::command]
-pub fn start_sync(window: Window, config: Option<SyncConfig>) -> Result<SyncStatus, String> {
+pub async fn start_sync(window: Window, config: Option<SyncConfig>) -> Result<SyncStatus, String> {
     let config = config.unwrap_or_default();
+    config.validate()?;
 
     {
         let process_guard = RCLONE_PROCESS
@@ -98,62 +346,57 @@ pub fn start_sync(window: Window, config: Option<SyncConfig>) -> Result<SyncStat
             .map_err(|e| format!("Failed to create local directory: {e}"))?;
     }
 
-    let remote_spec = format!("{}:{}", config.remote_name, config.remote_path);
+    let filter_rules = render_rules(&config)?;
+    let resync = needs_resync(&config, &filter_rules);
+    let output = Arc::new(Mutex::new(RunOutput::default()));
 
-    let cmd_result = match config.sync_mode {
-        SyncMode::Push => SafeCommand::new("rclone")
-            .and_then(|c| c.arg("sync"))
-            .and_then(|c| c.arg(&config.local_path))
-            .and_then(|c| c.arg(&remote_spec)),
-        SyncMode::Pull => SafeCommand::new("rclone")
-            .and_then(|c| c.arg("sync"))
-            .and_then(|c| c.arg(&remote_spec))
-            .and_then(|c| c.arg(&config.local_path)),
-        SyncMode::Bisync => SafeCommand::new("rclone")
-            .and_then(|c| c.arg("bisync"))
-            .and_then(|c| c.arg(&config.local_path))
-            .and_then(|c| c.arg(&remote_spec))
-            .and_then(|c| c.arg("--resync")),
+    let runner = match config.backend {
+        SyncBackend::Process => spawn_sync_process(&config, resync, &filter_rules, &output)?,
+        SyncBackend::RemoteControl => start_rc_job(&config, resync, &filter_rules).await?,
     };
+    let backend = config.backend;
 
-    let mut cmd_builder = cmd_result
-        .and_then(|c| c.arg("--progress"))
-        .and_then(|c| c.arg("--verbose"))
-        .and_then(|c| c.arg("--checksum"))
-        .map_err(|e| format!("Failed to build rclone command: {e}"))?;
-
-    for pattern in &config.exclude_patterns {
-        cmd_builder = cmd_builder
-            .arg("--exclude")
-            .and_then(|c| c.arg(pattern))
-            .map_err(|e| format!("Invalid exclude pattern: {e}"))?;
-    }
-
-    let child = cmd_builder
-        .stdout(Stdio::piped())
-        .stderr(Stdio::piped())
-        .spawn()
-        .map_err(|e| {
-            let err_str = e.to_string();
-            if err_str.contains("NotFound") || err_str.contains("not found") {
-                "rclone not found. Please install rclone: https://rclone.org/install/".to_string()
-            } else {
-                format!("Failed to start rclone: {e}")
-            }
-        })?;
+    let process = SyncProcess {
+        run_id: uuid::Uuid::new_v4().simple().to_string(),
+        started_at: chrono::Utc::now(),
+        runner,
+        state: ProcessState::Running,
+        config,
+        filter_rules,
+        output,
+    };
 
-    {
+    // Another sync may have started while this one was being set up.
+    let rejected = {
         let mut process_guard = RCLONE_PROCESS
             .lock()
             .unwrap_or_else(std::sync::PoisonError::into_inner);
-        *process_guard = Some(child);
+        if process_guard.is_some() {
+            Some(process)
+        } else {
+            *process_guard = Some(process);
+            None
+        }
+    };
+    if let Some(process) = rejected {
+        process.runner.abort().await;
+        return Err("Sync already running".to_string());
     }
 
     let _ = window.emit("sync_started", ());
 
-    std::thread::spawn(move || {
-        monitor_sync_process(&window);
-    });
+    match backend {
+        SyncBackend::Process => {
+            tokio::spawn(async move {
+                monitor_sync_process(&window).await;
+            });
+        }
+        SyncBackend::RemoteControl => {
+            tokio::spawn(async move {
+                monitor_rc_job(&window).await;
+            });
+        }
+    }
 
     Ok(SyncStatus {
         status: "syncing".to_string(),
@@ -166,21 +409,175 @@ pub fn start_sync(window: Window, config: Option<SyncConfig>) -> Result<SyncStat
     })
 }
 
+fn spawn_sync_process(
+    config: &SyncConfig,
+    resync: bool,
+    filter_rules: &[String],
+    output: &Arc<Mutex<RunOutput>>,
+) -> Result<Runner, String> {
+    let mut child = build_sync_command(config, resync, filter_rules)?
+        .arg("--use-json-log")
+        .and_then(|c| c.arg("--stats"))
+        .and_then(|c| c.arg("1s"))
+        .map_err(|e| format!("Failed to build rclone command: {e}"))?
+        .sandbox(Sandbox::RCLONE)
+        .spawn_async()
+        .map_err(|e| {
+            let err_str = e.to_string();
+            if err_str.contains("NotFound") || err_str.contains("not found") {
+                "rclone not found. Install it from the app settings or https://rclone.org/install/"
+                    .to_string()
+            } else {
+                format!("Failed to start rclone: {e}")
+            }
+        })?;
+
+    let mut readers = Vec::new();
+    if let Some(stdout) = child.stdout_lines() {
+        readers.push(spawn_output_reader(stdout, Arc::clone(output)));
+    }
+    if let Some(stderr) = child.stderr_lines() {
+        readers.push(spawn_output_reader(stderr, Arc::clone(output)));
+    }
+
+    Ok(Runner::Process { child, readers })
+}
+
+async fn start_rc_job(
+    config: &SyncConfig,
+    resync: bool,
+    filter_rules: &[String],
+) -> Result<Runner, String> {
+    let client = rc::ensure_daemon().await?;
+    if !client.list_remotes().await?.contains(&config.remote_name) {
+        return Err(format!("Remote {} is not configured", config.remote_name));
+    }
+
+    let filter_file = if filter_rules.is_empty() {
+        None
+    } else {
+        Some(write_filter_file(&config.profile, filter_rules)?)
+    };
+    let (method, params) = rc_sync_params(config, resync, filter_file.as_deref());
+    // Whatever the daemon logged before belongs to earlier runs.
+    rc::take_log();
+    let job_id = client.start_job(method, params).await?;
+
+    Ok(Runner::RemoteControl { client, job_id })
+}
+
+/// Compare both sides with `rclone check --combined` and report what
+/// `start_sync` would change, without touching any file.
+///
+/// For `Bisync` the report cannot tell deletions apart from new files, so
+/// files present on one side only are listed as copies.
+///
+/// # Errors
+/// Returns an error if rclone cannot be started or fails without producing
+/// a report.
 #[tauri::command]
-pub fn stop_sync() -> Result<SyncStatus, String> {
-    let mut process_guard = RCLONE_PROCESS
-        .lock()
-        .unwrap_or_else(std::sync::PoisonError::into_inner);
+pub fn preview_sync(config: Option<SyncConfig>) -> Result<SyncPreview, String> {
+    let config = config.unwrap_or_default();
+    config.validate()?;
 
-    process_guard
-        .take()
-        .ok_or_else(|| "No sync process running".to_string())
-        .map(|mut child| {
-            let _ = child.kill();
-            std::thread::sleep(std::time::Duration::from_millis(500));
-            let _ = child.wait();
+    let filter_rules = render_rules(&config)?;
+    let filter_file = if filter_rules.is_empty() {
+        None
+    } else {
+        Some(write_preview_filter_file(&config.profile, &filter_rules)?)
+    };
 
-            SyncStatus {
+    let output = check_command(rclone_command()?, &config, filter_file.as_deref())?
+        .timeout(PREVIEW_TIMEOUT)
+        .max_output(PREVIEW_MAX_OUTPUT)
+        .output()
+        .map_err(|e| format!("Failed to run rclone check: {e}"))?;
+
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let preview = parse_combined_report(config.sync_mode, &stdout);
+
+    // `rclone check` exits non-zero whenever differences are found, so only
+    // treat the run as failed when it produced no report at all.
+    if !output.status.success() && stdout.trim().is_empty() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        return Err(format!("rclone check failed: {}", stderr.trim()));
+    }
+
+    Ok(preview)
+}
+
+/// Stop the running sync. rclone first gets SIGINT, which lets it finish
+/// the current transfer and lets bisync save its listings; it is killed only
+/// if it is still running after the grace period. Where signals are not
+/// available the process is killed right away.
+///
+/// # Errors
+/// Returns an error if no sync is running.
+#[tauri::command]
+pub async fn stop_sync(grace_seconds: Option<u64>) -> Result<SyncStatus, String> {
+    let grace = Duration::from_secs(grace_seconds.unwrap_or(DEFAULT_STOP_GRACE_SECS));
+
+    let (run_id, cancel) = {
+        let mut process_guard = RCLONE_PROCESS
+            .lock()
+            .unwrap_or_else(std::sync::PoisonError::into_inner);
+        let process = process_guard
+            .as_mut()
+            .ok_or_else(|| "No sync process running".to_string())?;
+
+        let mut cancel = None;
+        if process.state != ProcessState::Stopping {
+            match &mut process.runner {
+                Runner::Process { child, .. } => {
+                    let interrupted = send_signal(child, Signal::Interrupt);
+                    if process.state == ProcessState::Paused {
+                        send_signal(child, Signal::Continue);
+                    }
+                    if !interrupted {
+                        child.start_kill();
+                    }
+                }
+                Runner::RemoteControl { client, job_id } => {
+                    cancel = Some((client.clone(), *job_id));
+                }
+            }
+            process.state = ProcessState::Stopping;
+        }
+        (process.run_id.clone(), cancel)
+    };
+
+    if let Some((client, job_id)) = cancel {
+        if let Err(e) = client.stop_job(job_id).await {
+            log::warn!("Failed to cancel rclone job {job_id}: {e}");
+        }
+    }
+
+    // The monitor reaps the process or job and records the run; wait for it,
+    // escalating to a kill once the grace period is over.
+    let deadline = Instant::now() + grace;
+    let mut killed = false;
+    loop {
+        tokio::time::sleep(STOP_POLL_INTERVAL).await;
+
+        let mut process_guard = RCLONE_PROCESS
+            .lock()
+            .unwrap_or_else(std::sync::PoisonError::into_inner);
+        match process_guard.as_mut() {
+            Some(process) if process.run_id == run_id => {
+                if !killed && Instant::now() >= deadline {
+                    log::warn!("rclone did not stop within {grace:?}, killing it");
+                    process.runner.kill();
+                    killed = true;
+                }
+            }
+            _ => break,
+        }
+    }
+
+    Ok(history::last_run(None)
+        .filter(|run| run.id == run_id)
+        .map_or_else(
+            || SyncStatus {
                 status: "stopped".to_string(),
                 is_running: false,
                 last_sync: Some(chrono::Utc::now().to_rfc3339()),
@@ -188,57 +585,85 @@ pub fn stop_sync() -> Result<SyncStatus, String> {
                 bytes_transferred: 0,
                 current_file: None,
                 error: None,
-            }
-        })
+            },
+            |run| SyncStatus {
+                status: run.status,
+                is_running: false,
+                last_sync: Some(run.finished_at),
+                files_synced: run.files_transferred,
+                bytes_transferred: run.bytes_transferred,
+                current_file: None,
+                error: run.errors.into_iter().next(),
+            },
+        ))
 }
 
+/// Suspend the running rclone process without losing its progress.
+///
+/// # Errors
+/// Returns an error if no sync is running, it is being stopped, or the
+/// platform cannot suspend processes.
 #[tauri::command]
-pub fn configure_remote(
-    remote_name: &str,
-    endpoint: &str,
-    access_key: &str,
-    secret_key: &str,
-    bucket: &str,
-) -> Result<(), String> {
-    let output = SafeCommand::new("rclone")
-        .and_then(|c| c.arg("config"))
-        .and_then(|c| c.arg("create"))
-        .and_then(|c| c.arg(remote_name))
-        .and_then(|c| c.arg("s3"))
-        .and_then(|c| c.arg("provider"))
-        .and_then(|c| c.arg("Minio"))
-        .and_then(|c| c.arg("endpoint"))
-        .and_then(|c| c.arg(endpoint))
-        .and_then(|c| c.arg("access_key_id"))
-        .and_then(|c| c.arg(access_key))
-        .and_then(|c| c.arg("secret_access_key"))
-        .and_then(|c| c.arg(secret_key))
-        .and_then(|c| c.arg("acl"))
-        .and_then(|c| c.arg("private"))
-        .map_err(|e| format!("Failed to build rclone command: {e}"))?
-        .output()
-        .map_err(|e| format!("Failed to configure rclone: {e}"))?;
+pub fn pause_sync() -> Result<SyncStatus, String> {
+    set_paused(true)
+}
 
-    if !output.status.success() {
-        let stderr = String::from_utf8_lossy(&output.stderr);
-        return Err(format!("rclone config failed: {stderr}"));
-    }
+/// Continue a sync suspended by `pause_sync`.
+///
+/// # Errors
+/// Returns an error if no sync is running or it is being stopped.
+#[tauri::command]
+pub fn resume_sync() -> Result<SyncStatus, String> {
+    set_paused(false)
+}
 
-    let _ = SafeCommand::new("rclone")
-        .and_then(|c| c.arg("config"))
-        .and_then(|c| c.arg("update"))
-        .and_then(|c| c.arg(remote_name))
-        .and_then(|c| c.arg("bucket"))
-        .and_then(|c| c.arg(bucket))
-        .and_then(|c| c.output());
+fn set_paused(paused: bool) -> Result<SyncStatus, String> {
+    let mut process_guard = RCLONE_PROCESS
+        .lock()
+        .unwrap_or_else(std::sync::PoisonError::into_inner);
+    let process = process_guard
+        .as_mut()
+        .ok_or_else(|| "No sync process running".to_string())?;
 
-    Ok(())
+    let (from, to, signal) = if paused {
+        (ProcessState::Running, ProcessState::Paused, Signal::Stop)
+    } else {
+        (
+            ProcessState::Paused,
+            ProcessState::Running,
+            Signal::Continue,
+        )
+    };
+
+    if process.state == ProcessState::Stopping {
+        return Err("Sync is stopping".to_string());
+    }
+    if process.state == from {
+        if !process.runner.signal(signal) {
+            return Err(match process.runner {
+                Runner::Process { .. } => "Pausing sync is not supported on this platform",
+                Runner::RemoteControl { .. } => {
+                    "Pausing sync is not supported by the remote-control backend"
+                }
+            }
+            .to_string());
+        }
+        process.state = to;
+    }
+
+    Ok(progress_status(&process.output, process.state))
 }
 
+/// Report the installed rclone version and which sync modes and backends
+/// it supports, so the UI can disable the others.
+///
+/// # Errors
+/// Returns an error if rclone is not installed or its version cannot be
+/// read.
 #[tauri::command]
-pub fn check_rclone_installed() -> Result<String, String> {
-    let output = SafeCommand::new("rclone")
-        .and_then(|c| c.arg("version"))
+pub fn check_rclone_installed() -> Result<RcloneCapabilities, String> {
+    let output = rclone_command()?
+        .arg("version")
         .map_err(|e| format!("Failed to build rclone command: {e}"))?
         .output()
         .map_err(|e| {
@@ -250,33 +675,18 @@ pub fn check_rclone_installed() -> Result<String, String> {
             }
         })?;
 
-    if output.status.success() {
-        let version = String::from_utf8_lossy(&output.stdout);
-        let first_line = version.lines().next().unwrap_or("unknown");
-        Ok(first_line.to_string())
-    } else {
-        Err("rclone check failed".to_string())
+    if !output.status.success() {
+        return Err("rclone check failed".to_string());
     }
-}
 
-#[tauri::command]
-pub fn list_remotes() -> Result<Vec<String>, String> {
-    let output = SafeCommand::new("rclone")
-        .and_then(|c| c.arg("listremotes"))
-        .map_err(|e| format!("Failed to build rclone command: {e}"))?
-        .output()
-        .map_err(|e| format!("Failed to list remotes: {e}"))?;
-
-    if output.status.success() {
-        let remotes = String::from_utf8_lossy(&output.stdout);
-        Ok(remotes
-            .lines()
-            .map(|s| s.trim_end_matches(':').to_string())
-            .filter(|s| !s.is_empty())
-            .collect())
-    } else {
-        Err("Failed to list rclone remotes".to_string())
-    }
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let version_line = stdout.lines().next().unwrap_or_default();
+    let version = RcloneVersion::parse(version_line)
+        .ok_or_else(|| format!("Unrecognized rclone version: {version_line}"))?;
+
+    let mut capabilities = RcloneCapabilities::for_version(version, version_line);
+    capabilities.managed = rclone_program() != "rclone";
+    Ok(capabilities)
 }
 
 #[tauri::command]
@@ -303,82 +713,1004 @@ pub fn set_sync_folder(path: &str) -> Result<(), String> {
     Ok(())
 }
 
-fn monitor_sync_process(window: &Window) {
+/// Read the persisted state of a sync profile.
+#[tauri::command]
+#[must_use]
+pub fn get_profile_state(profile: &str) -> ProfileState {
+    load_profiles().remove(profile).unwrap_or_default()
+}
+
+/// Forget the bisync listings of a profile so its next run uses `--resync`.
+///
+/// # Errors
+/// Returns an error if the profile state cannot be saved.
+#[tauri::command]
+pub fn reset_bisync(profile: &str) -> Result<(), String> {
+    update_profile(profile, |state| state.bisync_paths = None)
+}
+
+fn load_profiles() -> HashMap<String, ProfileState> {
+    storage::load_json(PROFILES_FILE)
+}
+
+fn update_profile(profile: &str, update: impl FnOnce(&mut ProfileState)) -> Result<(), String> {
+    let mut profiles = load_profiles();
+    update(profiles.entry(profile.to_string()).or_default());
+    storage::save_json(PROFILES_FILE, &profiles)
+}
+
+/// Profile names are used in file names, so only ASCII letters, digits,
+/// `_` and `-` are accepted.
+///
+/// # Errors
+/// Returns an error describing why the name is rejected.
+pub fn validate_profile_name(profile: &str) -> Result<(), String> {
+    let valid = !profile.is_empty()
+        && profile.len() <= 64
+        && profile
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-');
+
+    if valid {
+        Ok(())
+    } else {
+        Err(format!(
+            "Invalid profile name {profile:?}: use 1 to 64 letters, digits, '_' or '-'"
+        ))
+    }
+}
+
+impl SyncConfig {
+    /// Check the configuration before any rclone command is built.
+    ///
+    /// # Errors
+    /// Returns a message naming the first invalid field.
+    pub fn validate(&self) -> Result<(), String> {
+        validate_profile_name(&self.profile)?;
+
+        if let Some(limit) = &self.bandwidth_limit {
+            validate_bwlimit(limit)?;
+        }
+        if let Some(transfers) = self.transfers {
+            validate_range("transfers", transfers, MAX_TRANSFERS)?;
+        }
+        if let Some(checkers) = self.checkers {
+            validate_range("checkers", checkers, MAX_CHECKERS)?;
+        }
+        if let Some(max_age) = &self.max_age {
+            // Files ageing out of the filter would look deleted to bisync.
+            if self.sync_mode == SyncMode::Bisync {
+                return Err("max_age cannot be used with bisync".to_string());
+            }
+            validate_age(max_age)?;
+        }
+
+        let min_size = self.min_size.as_deref().map(parse_size).transpose()?;
+        let max_size = self.max_size.as_deref().map(parse_size).transpose()?;
+        if let (Some(min), Some(max)) = (min_size, max_size) {
+            if min > max {
+                return Err("min_size must not be larger than max_size".to_string());
+            }
+        }
+
+        Ok(())
+    }
+
+    fn remote_spec(&self) -> String {
+        format!("{}:{}", self.remote_name, self.remote_path)
+    }
+
+    fn bisync_paths(&self) -> String {
+        format!("{}|{}", self.local_path, self.remote_spec())
+    }
+}
+
+fn needs_resync(config: &SyncConfig, filter_rules: &[String]) -> bool {
+    if config.sync_mode != SyncMode::Bisync {
+        return false;
+    }
+    if config.force_resync {
+        return true;
+    }
+    let state = get_profile_state(&config.profile);
+    state.bisync_paths.as_deref() != Some(&config.bisync_paths())
+        || state.bisync_filters.as_deref() != Some(&filter_rules.join("\n"))
+}
+
+fn is_resync_required(log_lines: &VecDeque<String>) -> bool {
+    log_lines.iter().any(|line| {
+        let line = line.to_lowercase();
+        RESYNC_REQUIRED_MARKERS
+            .iter()
+            .any(|marker| line.contains(marker))
+    })
+}
+
+fn spawn_output_reader<R: AsyncBufRead + Unpin + Send + 'static>(
+    mut lines: Lines<R>,
+    output: Arc<Mutex<RunOutput>>,
+) -> JoinHandle<()> {
+    tokio::spawn(async move {
+        while let Ok(Some(line)) = lines.next_line().await {
+            output
+                .lock()
+                .unwrap_or_else(std::sync::PoisonError::into_inner)
+                .push_line(line);
+        }
+    })
+}
+
+impl RunOutput {
+    fn apply_job_stats(&mut self, stats: JobStats) {
+        self.files_transferred = stats.transfers;
+        self.bytes_transferred = stats.bytes;
+        self.error_count = stats.errors;
+        self.current_file = stats.transferring.into_iter().next().map(|t| t.name);
+        if let Some(error) = stats.last_error {
+            self.push_error(&error);
+        }
+    }
+
+    /// Record an error reported outside the log, as the rc API does.
+    fn push_error(&mut self, error: &str) {
+        let error = error.trim();
+        if error.is_empty() || self.errors.last().is_some_and(|last| last == error) {
+            return;
+        }
+        if self.errors.len() < MAX_RUN_ERRORS {
+            self.errors.push(error.to_string());
+        }
+        self.push_tail(format!(
+            "{} ERROR: {error}",
+            chrono::Utc::now().to_rfc3339()
+        ));
+    }
+
+    fn push_line(&mut self, line: String) {
+        let text = match serde_json::from_str::<LogLine>(&line) {
+            Ok(entry) => {
+                if let Some(stats) = entry.stats {
+                    self.files_transferred = stats.transfers;
+                    self.bytes_transferred = stats.bytes;
+                    self.error_count = stats.errors;
+                }
+                if entry.level == "error" && self.errors.len() < MAX_RUN_ERRORS {
+                    self.errors.push(entry.msg.trim().to_string());
+                }
+                if entry.object.is_some() {
+                    self.current_file = entry.object;
+                }
+                format!(
+                    "{} {}: {}",
+                    entry.time,
+                    entry.level.to_uppercase(),
+                    entry.msg.trim_end()
+                )
+            }
+            Err(_) => line,
+        };
+
+        self.push_tail(text);
+    }
+
+    fn push_tail(&mut self, text: String) {
+        if self.tail.len() == LOG_TAIL_LINES {
+            self.tail.pop_front();
+        }
+        self.tail.push_back(text);
+    }
+}
+
+#[cfg(unix)]
+#[allow(unsafe_code)]
+fn send_signal(child: &SafeChild, signal: Signal) -> bool {
+    let Some(Ok(pid)) = child.id().map(libc::pid_t::try_from) else {
+        return false;
+    };
+    let signal = match signal {
+        Signal::Interrupt => libc::SIGINT,
+        Signal::Stop => libc::SIGSTOP,
+        Signal::Continue => libc::SIGCONT,
+    };
+    // SAFETY: kill(2) has no memory-safety preconditions. Callers hold the
+    // process lock, so the child has not been reaped and its pid not reused.
+    unsafe { libc::kill(pid, signal) == 0 }
+}
+
+#[cfg(not(unix))]
+fn send_signal(_child: &SafeChild, _signal: Signal) -> bool {
+    false
+}
+
+fn progress_status(output: &Mutex<RunOutput>, state: ProcessState) -> SyncStatus {
+    let output = output
+        .lock()
+        .unwrap_or_else(std::sync::PoisonError::into_inner);
+
+    SyncStatus {
+        status: state.as_status().to_string(),
+        is_running: true,
+        last_sync: None,
+        files_synced: output.files_transferred,
+        bytes_transferred: output.bytes_transferred,
+        current_file: output.current_file.clone(),
+        error: None,
+    }
+}
+
+/// Wrap up a sync process that is no longer running: update the bisync
+/// state of its profile, record it in the history and build the final status.
+fn complete_run(process: SyncProcess, outcome: RunOutcome) -> SyncStatus {
+    let SyncProcess {
+        run_id,
+        started_at,
+        config,
+        filter_rules,
+        output,
+        ..
+    } = process;
+    let output = output
+        .lock()
+        .unwrap_or_else(std::sync::PoisonError::into_inner);
+
+    let (success, exit_code) = match &outcome {
+        RunOutcome::Exited(exit_status) => (exit_status.success(), exit_status.code()),
+        RunOutcome::JobFinished(result) => (result.is_ok(), None),
+        RunOutcome::Stopped | RunOutcome::Failed(_) => (false, None),
+    };
+    let bisync = config.sync_mode == SyncMode::Bisync;
+    let resync_required = bisync
+        && matches!(outcome, RunOutcome::Exited(_) | RunOutcome::JobFinished(_))
+        && !success
+        && is_resync_required(&output.tail);
+
+    if bisync && (success || resync_required) {
+        let saved = update_profile(&config.profile, |state| {
+            state.bisync_paths = success.then(|| config.bisync_paths());
+            state.bisync_filters = success.then(|| filter_rules.join("\n"));
+        });
+        if let Err(e) = saved {
+            log::warn!("Failed to save sync profile {}: {e}", config.profile);
+        }
+    }
+
+    let (status, error) = match outcome {
+        RunOutcome::Exited(_) | RunOutcome::JobFinished(_) if success => ("completed", None),
+        RunOutcome::Exited(_) | RunOutcome::JobFinished(_) if resync_required => (
+            "resync_required",
+            Some("Bisync listings are out of date, a resync is required".to_string()),
+        ),
+        RunOutcome::Exited(exit_status) => (
+            "error",
+            Some(format!("Exit code: {:?}", exit_status.code())),
+        ),
+        RunOutcome::JobFinished(result) => ("error", result.err()),
+        RunOutcome::Stopped => ("stopped", None),
+        RunOutcome::Failed(e) => ("error", Some(format!("Process error: {e}"))),
+    };
+
+    let finished_at = chrono::Utc::now().to_rfc3339();
+    let run = SyncRun {
+        id: run_id,
+        profile: config.profile.clone(),
+        sync_mode: config.sync_mode,
+        started_at: started_at.to_rfc3339(),
+        finished_at: finished_at.clone(),
+        status: status.to_string(),
+        exit_code,
+        files_transferred: output.files_transferred,
+        bytes_transferred: output.bytes_transferred,
+        error_count: output.error_count,
+        errors: output.errors.clone(),
+    };
+    let log: Vec<String> = output.tail.iter().cloned().collect();
+    if let Err(e) = history::record_run(run, &log) {
+        log::warn!("Failed to record sync history: {e}");
+    }
+
+    SyncStatus {
+        status: status.to_string(),
+        is_running: false,
+        last_sync: Some(finished_at),
+        files_synced: output.files_transferred,
+        bytes_transferred: output.bytes_transferred,
+        current_file: None,
+        error,
+    }
+}
+
+fn build_sync_command(
+    config: &SyncConfig,
+    resync: bool,
+    filter_rules: &[String],
+) -> Result<SafeCommand, String> {
+    let filter_file = if filter_rules.is_empty() {
+        None
+    } else {
+        Some(write_filter_file(&config.profile, filter_rules)?)
+    };
+    sync_command(rclone_command()?, config, resync, filter_file.as_deref())
+}
+
+/// Add the arguments of a sync run to `base`, an rclone command.
+fn sync_command(
+    base: SafeCommand,
+    config: &SyncConfig,
+    resync: bool,
+    filter_file: Option<&Path>,
+) -> Result<SafeCommand, String> {
+    let remote_spec = config.remote_spec();
+
+    let cmd_result = match config.sync_mode {
+        SyncMode::Push => base
+            .arg("sync")
+            .and_then(|c| c.arg(&config.local_path))
+            .and_then(|c| c.arg(&remote_spec)),
+        SyncMode::Pull => base
+            .arg("sync")
+            .and_then(|c| c.arg(&remote_spec))
+            .and_then(|c| c.arg(&config.local_path)),
+        SyncMode::Bisync => base
+            .arg("bisync")
+            .and_then(|c| c.arg(&config.local_path))
+            .and_then(|c| c.arg(&remote_spec))
+            .and_then(|c| if resync { c.arg("--resync") } else { Ok(c) }),
+    };
+
+    let mut cmd_builder = cmd_result
+        .and_then(|c| c.arg("--verbose"))
+        .and_then(|c| c.arg("--checksum"))
+        .map_err(|e| format!("Failed to build rclone command: {e}"))?;
+
+    let tuning = [
+        ("--bwlimit", config.bandwidth_limit.clone()),
+        ("--transfers", config.transfers.map(|n| n.to_string())),
+        ("--checkers", config.checkers.map(|n| n.to_string())),
+    ];
+    for (flag, value) in tuning {
+        if let Some(value) = value {
+            cmd_builder = cmd_builder
+                .arg(flag)
+                .and_then(|c| c.arg(&value))
+                .map_err(|e| format!("Invalid value for {flag}: {e}"))?;
+        }
+    }
+
+    let filter_flag = if config.sync_mode == SyncMode::Bisync {
+        "--filters-file"
+    } else {
+        "--filter-from"
+    };
+    apply_filters(cmd_builder, config, filter_flag, filter_file)
+}
+
+/// Add the arguments of the `rclone check` run behind `preview_sync` to
+/// `base`. `check` only reads filters with `--filter-from`, also for
+/// bisync profiles.
+fn check_command(
+    base: SafeCommand,
+    config: &SyncConfig,
+    filter_file: Option<&Path>,
+) -> Result<SafeCommand, String> {
+    let local = config.local_path.as_str();
+    let remote = config.remote_spec();
+
+    let (src, dst) = match config.sync_mode {
+        SyncMode::Push | SyncMode::Bisync => (local, remote.as_str()),
+        SyncMode::Pull => (remote.as_str(), local),
+    };
+
+    let cmd_builder = base
+        .arg("check")
+        .and_then(|c| c.arg(src))
+        .and_then(|c| c.arg(dst))
+        .and_then(|c| c.arg("--combined"))
+        .and_then(|c| c.arg("-"))
+        .map_err(|e| format!("Failed to build rclone command: {e}"))?;
+
+    apply_filters(cmd_builder, config, "--filter-from", filter_file)
+}
+
+/// Parameters of the rc call equivalent to `build_sync_command`. Tuning and
+/// size limits go in the per-call `_config` and `_filter` overrides.
+fn rc_sync_params(
+    config: &SyncConfig,
+    resync: bool,
+    filter_file: Option<&Path>,
+) -> (&'static str, Value) {
+    let local = config.local_path.as_str();
+    let remote = config.remote_spec();
+
+    let (method, mut params) = match config.sync_mode {
+        SyncMode::Push => ("sync/sync", json!({ "srcFs": local, "dstFs": remote })),
+        SyncMode::Pull => ("sync/sync", json!({ "srcFs": remote, "dstFs": local })),
+        SyncMode::Bisync => (
+            "sync/bisync",
+            json!({ "path1": local, "path2": remote, "resync": resync }),
+        ),
+    };
+
+    let mut options = json!({ "CheckSum": true });
+    if let Some(limit) = &config.bandwidth_limit {
+        options["BwLimit"] = json!(limit);
+    }
+    if let Some(transfers) = config.transfers {
+        options["Transfers"] = json!(transfers);
+    }
+    if let Some(checkers) = config.checkers {
+        options["Checkers"] = json!(checkers);
+    }
+
+    let mut filter = json!({});
+    let limits = [
+        ("MaxAge", &config.max_age),
+        ("MinSize", &config.min_size),
+        ("MaxSize", &config.max_size),
+    ];
+    for (key, value) in limits {
+        if let Some(value) = value {
+            filter[key] = json!(value);
+        }
+    }
+
+    if let Some(path) = filter_file {
+        if config.sync_mode == SyncMode::Bisync {
+            params["filtersFile"] = json!(path);
+        } else {
+            filter["FilterFrom"] = json!([path]);
+        }
+    }
+
+    params["_config"] = options;
+    params["_filter"] = filter;
+    (method, params)
+}
+
+/// Pass the profile's filter rules through a file, so patterns are not
+/// limited by argument validation. `filter_flag` depends on the subcommand:
+/// bisync takes the file with `--filters-file` to track changes between
+/// runs, the others with `--filter-from`.
+fn apply_filters(
+    mut cmd_builder: SafeCommand,
+    config: &SyncConfig,
+    filter_flag: &str,
+    filter_file: Option<&Path>,
+) -> Result<SafeCommand, String> {
+    let limits = [
+        ("--max-age", &config.max_age),
+        ("--min-size", &config.min_size),
+        ("--max-size", &config.max_size),
+    ];
+    for (flag, value) in limits {
+        if let Some(value) = value {
+            cmd_builder = cmd_builder
+                .arg(flag)
+                .and_then(|c| c.arg(value))
+                .map_err(|e| format!("Invalid value for {flag}: {e}"))?;
+        }
+    }
+
+    let Some(path) = filter_file else {
+        return Ok(cmd_builder);
+    };
+
+    cmd_builder
+        .arg(filter_flag)
+        .and_then(|c| c.arg(&path.to_string_lossy()))
+        .map_err(|e| format!("Invalid filter file path: {e}"))
+}
+
+fn validate_range(field: &str, value: u32, max: u32) -> Result<(), String> {
+    if value == 0 || value > max {
+        return Err(format!("{field} must be between 1 and {max}"));
+    }
+    Ok(())
+}
+
+/// Parse an rclone size such as `100`, `1.5M`, `2Gi` or `512KiB` into bytes.
+/// Sizes without a suffix are in KiB, and every suffix is binary, as in
+/// rclone.
+///
+/// # Errors
+/// Returns an error if `value` is not a size.
+pub fn parse_size(value: &str) -> Result<u64, String> {
+    let invalid = || format!("Invalid size: {value}");
+    let value = value.trim();
+
+    let number = value.trim_end_matches(|c: char| c.is_ascii_alphabetic());
+    let suffix = value[number.len()..].to_ascii_uppercase();
+    let exponent = match suffix.as_str() {
+        "" => 1,
+        "B" => 0,
+        _ => {
+            let unit = suffix.strip_suffix('B').unwrap_or(&suffix);
+            match unit.strip_suffix('I').unwrap_or(unit) {
+                "K" => 1,
+                "M" => 2,
+                "G" => 3,
+                "T" => 4,
+                "P" => 5,
+                _ => return Err(invalid()),
+            }
+        }
+    };
+
+    if number.is_empty() || !number.chars().all(|c| c.is_ascii_digit() || c == '.') {
+        return Err(invalid());
+    }
+    let number: f64 = number.parse().map_err(|_| invalid())?;
+
+    #[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+    Ok((number * 1024_f64.powi(exponent)) as u64)
+}
+
+/// Validate a `--bwlimit` value: whitespace-separated entries that are
+/// either a rate or `[Day-]HH:MM,rate`, where a rate is `off`, a size, or an
+/// upload:download pair of those.
+fn validate_bwlimit(value: &str) -> Result<(), String> {
+    const DAYS: [&str; 7] = ["Mon", "Tue", "Wed", "Thu", "Fri", "Sat", "Sun"];
+    let invalid = || format!("Invalid bandwidth limit: {value}");
+
+    let entries: Vec<&str> = value.split_whitespace().collect();
+    if entries.is_empty() {
+        return Err(invalid());
+    }
+
+    for entry in &entries {
+        let rate = match entry.split_once(',') {
+            Some((time, rate)) => {
+                let clock = match time.split_once('-') {
+                    Some((day, clock)) if DAYS.contains(&day) => clock,
+                    Some(_) => return Err(invalid()),
+                    None => time,
+                };
+                chrono::NaiveTime::parse_from_str(clock, "%H:%M").map_err(|_| invalid())?;
+                rate
+            }
+            None if entries.len() == 1 => entry,
+            None => return Err(invalid()),
+        };
+
+        let (up, down) = rate.split_once(':').unwrap_or((rate, rate));
+        for part in [up, down] {
+            if part != "off" {
+                parse_size(part).map_err(|_| invalid())?;
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Validate a `--max-age` value: an rclone duration made of number and unit
+/// pairs (`ms`, `s`, `m`, `h`, `d`, `w`, `M`, `y`), a bare number of
+/// seconds, or a date.
+///
+/// # Errors
+/// Returns an error if `value` is none of these.
+pub fn validate_age(value: &str) -> Result<(), String> {
+    let invalid = || format!("Invalid max_age: {value}");
+
+    let seconds = value.chars().all(|c| c.is_ascii_digit() || c == '.');
+    if seconds && value.parse::<f64>().is_ok()
+        || chrono::NaiveDate::parse_from_str(value, "%Y-%m-%d").is_ok()
+        || chrono::DateTime::parse_from_rfc3339(value).is_ok()
+    {
+        return Ok(());
+    }
+
+    let mut rest = value;
+    if rest.is_empty() {
+        return Err(invalid());
+    }
+    while !rest.is_empty() {
+        let digits = rest
+            .find(|c: char| !c.is_ascii_digit() && c != '.')
+            .ok_or_else(invalid)?;
+        if digits == 0 {
+            return Err(invalid());
+        }
+        rest = &rest[digits..];
+
+        let unit = ["ms", "s", "m", "h", "d", "w", "M", "y"]
+            .into_iter()
+            .find(|unit| rest.starts_with(unit))
+            .ok_or_else(invalid)?;
+        rest = &rest[unit.len()..];
+    }
+
+    Ok(())
+}
+
+/// Parse the output of `rclone check --combined -`, where each line is a
+/// status symbol followed by a path relative to the compared roots.
+fn parse_combined_report(sync_mode: SyncMode, report: &str) -> SyncPreview {
+    let mut preview = SyncPreview::new(sync_mode);
+
+    let (src_side, dst_side) = match sync_mode {
+        SyncMode::Push | SyncMode::Bisync => (SyncSide::Local, SyncSide::Remote),
+        SyncMode::Pull => (SyncSide::Remote, SyncSide::Local),
+    };
+
+    for line in report.lines() {
+        let Some((symbol, path)) = line.split_once(' ') else {
+            continue;
+        };
+        let item = |target| PreviewItem {
+            path: path.to_string(),
+            target,
+        };
+
+        match (symbol, sync_mode) {
+            ("=", _) => preview.unchanged += 1,
+            // `+`: only in the source, `-`: only in the destination.
+            ("+", _) => preview.to_copy.push(item(Some(dst_side))),
+            ("-", SyncMode::Bisync) => preview.to_copy.push(item(Some(src_side))),
+            ("-", _) => preview.to_delete.push(item(Some(dst_side))),
+            ("*", SyncMode::Bisync) => preview.to_update.push(item(None)),
+            ("*", _) => preview.to_update.push(item(Some(dst_side))),
+            ("!", _) => preview.errors.push(path.to_string()),
+            _ => {}
+        }
+    }
+
+    preview
+}
+
+async fn monitor_sync_process(window: &Window) {
     loop {
-        std::thread::sleep(std::time::Duration::from_secs(1));
+        tokio::time::sleep(Duration::from_secs(1)).await;
 
-        let mut process_guard = RCLONE_PROCESS
-            .lock()
-            .unwrap_or_else(std::sync::PoisonError::into_inner);
+        let (finished, outcome) = {
+            let mut process_guard = RCLONE_PROCESS
+                .lock()
+                .unwrap_or_else(std::sync::PoisonError::into_inner);
 
-        let status_opt = if let Some(ref mut child) = *process_guard {
-            match child.try_wait() {
-                Ok(Some(exit_status)) => {
-                    let success = exit_status.success();
-                    *process_guard = None;
-                    drop(process_guard);
+            let Some(process) = process_guard.as_mut() else {
+                return;
+            };
+            let Runner::Process { child, .. } = &mut process.runner else {
+                return;
+            };
 
-                    let status = SyncStatus {
-                        status: if success {
-                            "completed".to_string()
-                        } else {
-                            "error".to_string()
-                        },
-                        is_running: false,
-                        last_sync: Some(chrono::Utc::now().to_rfc3339()),
-                        files_synced: 0,
-                        bytes_transferred: 0,
-                        current_file: None,
-                        error: if success {
-                            None
-                        } else {
-                            Some(format!("Exit code: {:?}", exit_status.code()))
-                        },
-                    };
-
-                    if success {
-                        let _ = window.emit("sync_completed", &status);
-                    } else {
-                        let _ = window.emit("sync_error", &status);
-                    }
-                    return;
+            let outcome = match child.try_wait() {
+                // A run that finished cleanly before the stop took effect
+                // still counts, so bisync state gets recorded.
+                Ok(Some(exit_status))
+                    if process.state == ProcessState::Stopping && !exit_status.success() =>
+                {
+                    RunOutcome::Stopped
                 }
+                Ok(Some(exit_status)) => RunOutcome::Exited(exit_status),
                 Ok(None) => {
+                    let output = Arc::clone(&process.output);
+                    let state = process.state;
                     drop(process_guard);
-                    Some(SyncStatus {
-                        status: "syncing".to_string(),
-                        is_running: true,
-                        last_sync: None,
-                        files_synced: 0,
-                        bytes_transferred: 0,
-                        current_file: None,
-                        error: None,
-                    })
+                    let _ = window.emit("sync_progress", &progress_status(&output, state));
+                    continue;
                 }
-                Err(e) => {
-                    *process_guard = None;
-                    drop(process_guard);
+                Err(e) => RunOutcome::Failed(e.to_string()),
+            };
 
-                    let status = SyncStatus {
-                        status: "error".to_string(),
-                        is_running: false,
-                        last_sync: Some(chrono::Utc::now().to_rfc3339()),
-                        files_synced: 0,
-                        bytes_transferred: 0,
-                        current_file: None,
-                        error: Some(format!("Process error: {e}")),
-                    };
-                    let _ = window.emit("sync_error", &status);
-                    return;
+            (process_guard.take(), outcome)
+        };
+
+        if let Some(mut process) = finished {
+            // Let the readers drain the pipes so the log is complete.
+            if let Runner::Process { readers, .. } = &mut process.runner {
+                for reader in readers.drain(..) {
+                    let _ = reader.await;
                 }
             }
-        } else {
-            drop(process_guard);
+            emit_completion(window, complete_run(process, outcome));
+        }
+        return;
+    }
+}
+
+/// Poll the rc job of the current sync until it finishes, mirroring
+/// `monitor_sync_process`.
+async fn monitor_rc_job(window: &Window) {
+    loop {
+        tokio::time::sleep(Duration::from_secs(1)).await;
+
+        let (run_id, client, job_id, output) = {
+            let process_guard = RCLONE_PROCESS
+                .lock()
+                .unwrap_or_else(std::sync::PoisonError::into_inner);
+            match process_guard.as_ref() {
+                Some(SyncProcess {
+                    run_id,
+                    runner: Runner::RemoteControl { client, job_id },
+                    output,
+                    ..
+                }) => (run_id.clone(), client.clone(), *job_id, Arc::clone(output)),
+                _ => return,
+            }
+        };
+
+        let status = client.job_status(job_id).await;
+        let stats = client.job_stats(job_id).await;
+        {
+            let mut output = output
+                .lock()
+                .unwrap_or_else(std::sync::PoisonError::into_inner);
+            for line in rc::take_log() {
+                output.push_line(line);
+            }
+            if let Ok(stats) = stats {
+                output.apply_job_stats(stats);
+            }
+        }
+
+        let mut outcome = match status {
+            Ok(status) if !status.finished => None,
+            Ok(status) if status.success => Some(RunOutcome::JobFinished(Ok(()))),
+            Ok(status) => {
+                output
+                    .lock()
+                    .unwrap_or_else(std::sync::PoisonError::into_inner)
+                    .push_error(&status.error);
+                Some(RunOutcome::JobFinished(Err(status.error)))
+            }
+            Err(e) => Some(RunOutcome::Failed(e)),
+        };
+
+        let mut process_guard = RCLONE_PROCESS
+            .lock()
+            .unwrap_or_else(std::sync::PoisonError::into_inner);
+        let Some(process) = process_guard.as_ref().filter(|p| p.run_id == run_id) else {
             return;
         };
+        let succeeded = matches!(outcome, Some(RunOutcome::JobFinished(Ok(()))));
+        if outcome.is_some() && !succeeded && process.state == ProcessState::Stopping {
+            outcome = Some(RunOutcome::Stopped);
+        }
+
+        let Some(outcome) = outcome else {
+            let state = process.state;
+            drop(process_guard);
+            let _ = window.emit("sync_progress", &progress_status(&output, state));
+            continue;
+        };
+
+        let finished = process_guard.take();
+        drop(process_guard);
+
+        if let Some(process) = finished {
+            emit_completion(window, complete_run(process, outcome));
+        }
+        return;
+    }
+}
+
+fn emit_completion(window: &Window, status: SyncStatus) {
+    let event = match status.status.as_str() {
+        "completed" => "sync_completed",
+        "stopped" => "sync_stopped",
+        _ => "sync_error",
+    };
+    let _ = window.emit(event, &status);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// `new.txt` is only in the source, `extra.txt` only in the destination.
+    const REPORT: &str = "= same.txt\n+ new.txt\n- extra.txt\n* changed.txt\n! broken.txt\n";
+
+    #[test]
+    fn test_preview_push() {
+        let preview = parse_combined_report(SyncMode::Push, REPORT);
+        assert_eq!(preview.unchanged, 1);
+        assert_eq!(
+            preview.to_copy,
+            vec![PreviewItem {
+                path: "new.txt".to_string(),
+                target: Some(SyncSide::Remote),
+            }]
+        );
+        assert_eq!(preview.to_delete[0].path, "extra.txt");
+        assert_eq!(preview.to_delete[0].target, Some(SyncSide::Remote));
+        assert_eq!(preview.to_update[0].target, Some(SyncSide::Remote));
+        assert_eq!(preview.errors, vec!["broken.txt".to_string()]);
+    }
+
+    #[test]
+    fn test_preview_pull_targets_local() {
+        let preview = parse_combined_report(SyncMode::Pull, REPORT);
+        assert_eq!(preview.to_copy[0].path, "new.txt");
+        assert_eq!(preview.to_copy[0].target, Some(SyncSide::Local));
+        assert_eq!(preview.to_delete[0].path, "extra.txt");
+        assert_eq!(preview.to_delete[0].target, Some(SyncSide::Local));
+    }
+
+    #[test]
+    fn test_preview_bisync_never_deletes() {
+        let preview = parse_combined_report(SyncMode::Bisync, REPORT);
+        assert!(preview.to_delete.is_empty());
+        assert_eq!(
+            preview.to_copy,
+            vec![
+                PreviewItem {
+                    path: "new.txt".to_string(),
+                    target: Some(SyncSide::Remote),
+                },
+                PreviewItem {
+                    path: "extra.txt".to_string(),
+                    target: Some(SyncSide::Local),
+                },
+            ]
+        );
+        assert_eq!(preview.to_update[0].target, None);
+    }
+
+    #[test]
+    fn test_preview_keeps_spaces_in_paths() {
+        let preview = parse_combined_report(SyncMode::Push, "+ My Documents/a b.txt\n");
+        assert_eq!(preview.to_copy[0].path, "My Documents/a b.txt");
+        assert!(!preview.is_empty());
+    }
+
+    #[test]
+    fn test_parse_size() {
+        assert_eq!(parse_size("100"), Ok(100 * 1024));
+        assert_eq!(parse_size("512B"), Ok(512));
+        assert_eq!(parse_size("1.5M"), Ok(1_572_864));
+        assert_eq!(parse_size("2g"), Ok(2 * 1024 * 1024 * 1024));
+        assert!(parse_size("").is_err());
+        assert!(parse_size("10X").is_err());
+        assert_eq!(parse_size("1Ki"), Ok(1024));
+        assert_eq!(parse_size("1KiB"), Ok(1024));
+        assert_eq!(parse_size("2MB"), Ok(2 * 1024 * 1024));
+        assert_eq!(parse_size("3gib"), Ok(3 * 1024 * 1024 * 1024));
+        assert!(parse_size("1iB").is_err());
+        assert!(parse_size("1KiBB").is_err());
+        assert!(parse_size("-1M").is_err());
+    }
+
+    #[test]
+    fn test_validate_bwlimit() {
+        assert!(validate_bwlimit("4M").is_ok());
+        assert!(validate_bwlimit("1M:off").is_ok());
+        assert!(validate_bwlimit("08:00,512k 12:00,10M 23:00,off").is_ok());
+        assert!(validate_bwlimit("Mon-00:00,512 Fri-23:59,10M:1M Sat-10:00,off").is_ok());
+        assert!(validate_bwlimit("").is_err());
+        assert!(validate_bwlimit("fast").is_err());
+        assert!(validate_bwlimit("25:00,1M").is_err());
+        assert!(validate_bwlimit("Funday-08:00,1M").is_err());
+        assert!(validate_bwlimit("1M 2M").is_err());
+    }
+
+    #[test]
+    fn test_validate_age() {
+        assert!(validate_age("7d").is_ok());
+        assert!(validate_age("1h30m").is_ok());
+        assert!(validate_age("500ms").is_ok());
+        assert!(validate_age("2024-01-31").is_ok());
+        assert!(validate_age("").is_err());
+        assert!(validate_age("d").is_err());
+        assert!(validate_age("3600").is_ok());
+        assert!(validate_age("1.5").is_ok());
+        assert!(validate_age(".").is_err());
+        assert!(validate_age("7x").is_err());
+    }
+
+    #[test]
+    fn test_validate_tuning() {
+        let mut config = SyncConfig {
+            sync_mode: SyncMode::Push,
+            transfers: Some(8),
+            min_size: Some("1M".to_string()),
+            max_size: Some("1G".to_string()),
+            max_age: Some("30d".to_string()),
+            ..SyncConfig::default()
+        };
+        assert!(config.validate().is_ok());
+
+        config.transfers = Some(0);
+        assert!(config.validate().is_err());
 
-        if let Some(status) = status_opt {
-            let _ = window.emit("sync_progress", &status);
+        config.transfers = None;
+        config.min_size = Some("2G".to_string());
+        assert!(config.validate().is_err());
+
+        config.min_size = None;
+        config.sync_mode = SyncMode::Bisync;
+        assert!(config.validate().is_err());
+    }
+
+    #[test]
+    fn test_run_output_parses_json_log() {
+        let mut output = RunOutput::default();
+        output.push_line(
+            r#"{"time":"2026-10-18T10:00:00Z","level":"info","msg":"Copied (new)","object":"bots/a.txt"}"#
+                .to_string(),
+        );
+        output.push_line(
+            r#"{"time":"2026-10-18T10:00:01Z","level":"error","msg":"Failed to copy: denied\n"}"#
+                .to_string(),
+        );
+        output.push_line(
+            r#"{"time":"2026-10-18T10:00:02Z","level":"info","msg":"stats","stats":{"bytes":2048,"transfers":3,"errors":1}}"#
+                .to_string(),
+        );
+        output.push_line("plain text".to_string());
+
+        assert_eq!(output.files_transferred, 3);
+        assert_eq!(output.bytes_transferred, 2048);
+        assert_eq!(output.error_count, 1);
+        assert_eq!(output.errors, vec!["Failed to copy: denied".to_string()]);
+        assert_eq!(output.current_file.as_deref(), Some("bots/a.txt"));
+        assert_eq!(output.tail[0], "2026-10-18T10:00:00Z INFO: Copied (new)");
+        assert_eq!(output.tail[3], "plain text");
+    }
+
+    #[test]
+    fn test_resync_required_detection() {
+        let mut tail = VecDeque::from(["INFO  : Synching Path1 and Path2".to_string()]);
+        assert!(!is_resync_required(&tail));
+
+        tail.push_back("ERROR : Bisync aborted. Must run --resync to recover.".to_string());
+        assert!(is_resync_required(&tail));
+    }
+
+    /// Every option a profile can set must get through the rclone policy of
+    /// `SafeCommand`.
+    #[test]
+    fn test_sync_command_passes_policy() {
+        let filter_file = Path::new("/tmp/default.filter");
+        for sync_mode in [SyncMode::Push, SyncMode::Pull, SyncMode::Bisync] {
+            let config = SyncConfig {
+                local_path: "/home/user/GeneralBots".to_string(),
+                sync_mode,
+                bandwidth_limit: Some("08:00,512k 19:00,off".to_string()),
+                transfers: Some(8),
+                checkers: Some(16),
+                max_age: Some("30d".to_string()),
+                min_size: Some("1M".to_string()),
+                max_size: Some("1.5G".to_string()),
+                ..SyncConfig::default()
+            };
+            let base = SafeCommand::new("rclone").unwrap();
+            let cmd = sync_command(base, &config, true, Some(filter_file));
+            assert!(cmd.is_ok(), "{sync_mode:?}: {cmd:?}");
+
+            let base = SafeCommand::new("rclone").unwrap();
+            let cmd = check_command(base, &config, Some(filter_file));
+            assert!(cmd.is_ok(), "{sync_mode:?} preview: {cmd:?}");
+            assert!(format!("{cmd:?}").contains("--filter-from"));
         }
     }
+
+    #[test]
+    fn test_rc_sync_params() {
+        let config = SyncConfig {
+            local_path: "/home/user/GeneralBots".to_string(),
+            sync_mode: SyncMode::Push,
+            transfers: Some(8),
+            min_size: Some("1M".to_string()),
+            ..SyncConfig::default()
+        };
+        let (method, params) = rc_sync_params(&config, false, Some(Path::new("/tmp/p.filter")));
+        assert_eq!(method, "sync/sync");
+        assert_eq!(params["srcFs"], "/home/user/GeneralBots");
+        assert_eq!(params["dstFs"], "gbdrive:/");
+        assert_eq!(params["_config"]["CheckSum"], true);
+        assert_eq!(params["_config"]["Transfers"], 8);
+        assert_eq!(params["_filter"]["MinSize"], "1M");
+        assert_eq!(params["_filter"]["FilterFrom"][0], "/tmp/p.filter");
+
+        let config = SyncConfig {
+            sync_mode: SyncMode::Bisync,
+            ..config
+        };
+        let (method, params) = rc_sync_params(&config, true, Some(Path::new("/tmp/p.filter")));
+        assert_eq!(method, "sync/bisync");
+        assert_eq!(params["path2"], "gbdrive:/");
+        assert_eq!(params["resync"], true);
+        assert_eq!(params["filtersFile"], "/tmp/p.filter");
+        assert!(params["_filter"].get("FilterFrom").is_none());
+    }
 }
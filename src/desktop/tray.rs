@@ -6,7 +6,14 @@ use tauri::AppHandle;
 use tauri::tray::{TrayIcon, TrayIconBuilder};
 use tauri::menu::{Menu, MenuItem};
 
+#[cfg(target_os = "macos")]
+use crate::desktop::command_policy;
 use crate::desktop::safe_command::SafeCommand;
+#[cfg(any(target_os = "linux", target_os = "macos"))]
+use crate::desktop::sandbox::Sandbox;
+
+#[cfg(any(target_os = "linux", target_os = "macos"))]
+const NOTIFIER_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(10);
 
 #[derive(Clone)]
 pub struct TrayManager {
@@ -181,21 +188,26 @@ impl TrayManager {
             #[cfg(target_os = "linux")]
             {
                 if let Ok(cmd) = SafeCommand::new("notify-send")
+                    .and_then(|c| c.arg("--"))
                     .and_then(|c| c.arg(title))
                     .and_then(|c| c.arg(body))
                 {
-                    let _ = cmd.spawn();
+                    spawn_notifier(cmd);
                 }
             }
 
             #[cfg(target_os = "macos")]
             {
-                let script = format!("display notification \"{body}\" with title \"{title}\"");
+                let script = format!(
+                    "display notification {} with title {}",
+                    command_policy::applescript_string(body),
+                    command_policy::applescript_string(title)
+                );
                 if let Ok(cmd) = SafeCommand::new("osascript")
                     .and_then(|c| c.arg("-e"))
                     .and_then(|c| c.arg(&script))
                 {
-                    let _ = cmd.spawn();
+                    spawn_notifier(cmd);
                 }
             }
         }
@@ -344,3 +356,19 @@ impl Default for ServiceMonitor {
         Self::new()
     }
 }
+
+/// Run a notification command in the background, reaping it and killing it
+/// if it hangs.
+#[cfg(any(target_os = "linux", target_os = "macos"))]
+fn spawn_notifier(cmd: SafeCommand) {
+    match cmd.sandbox(Sandbox::HELPER).spawn_async() {
+        Ok(mut child) => {
+            tokio::spawn(async move {
+                if let Err(e) = child.wait_with_timeout(NOTIFIER_TIMEOUT).await {
+                    log::debug!("Notification command failed: {e}");
+                }
+            });
+        }
+        Err(e) => log::debug!("Failed to show notification: {e}"),
+    }
+}
@@ -181,9 +181,38 @@ fn main() {
             desktop::sync::get_sync_status,
             desktop::sync::start_sync,
             desktop::sync::stop_sync,
-            desktop::sync::configure_remote,
+            desktop::sync::pause_sync,
+            desktop::sync::resume_sync,
+            desktop::sync::preview_sync,
+            desktop::sync::get_profile_state,
+            desktop::sync::reset_bisync,
+            desktop::history::get_sync_history,
+            desktop::history::get_sync_run_log,
+            desktop::audit::export_audit_log,
+            desktop::filters::get_selective_sync,
+            desktop::filters::set_selective_sync,
+            desktop::filters::preview_sync_filters,
+            desktop::native_sync::native_sync,
+            desktop::native_sync::preview_native_sync,
+            desktop::native_sync::set_native_sync_secret,
+            desktop::conflicts::list_sync_conflicts,
+            desktop::conflicts::resolve_conflict,
             desktop::sync::check_rclone_installed,
-            desktop::sync::list_remotes,
+            desktop::remote::configure_remote,
+            desktop::remote::test_remote,
+            desktop::remote::list_remotes,
+            desktop::remote::get_remote,
+            desktop::remote::update_remote,
+            desktop::remote::delete_remote,
+            desktop::remote::rename_remote,
+            desktop::remote::list_remote_files,
+            desktop::remote::remote_about,
+            desktop::provisioning::get_rclone_install,
+            desktop::provisioning::get_rclone_source,
+            desktop::provisioning::install_rclone,
+            desktop::provisioning::uninstall_rclone,
+            desktop::rclone::get_config_encryption,
+            desktop::rclone::set_config_encryption,
             desktop::sync::get_sync_folder,
             desktop::sync::set_sync_folder,
             get_tray_status,
@@ -228,9 +257,14 @@ fn main() {
 
             Ok(())
         })
-        .run(tauri::generate_context!());
+        .build(tauri::generate_context!());
 
-    if let Err(e) = builder_result {
-        log::error!("Failed to run BotApp: {e}");
+    match builder_result {
+        Ok(app) => app.run(|_app, event| {
+            if let tauri::RunEvent::Exit = event {
+                desktop::rc::shutdown_daemon();
+            }
+        }),
+        Err(e) => log::error!("Failed to run BotApp: {e}"),
     }
 }